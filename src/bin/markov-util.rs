@@ -1,10 +1,17 @@
 #[warn(missing_docs)]
 #[cfg(feature = "generator")] extern crate markov_chain;
 #[cfg(feature = "generator")] extern crate serde;
+#[cfg(feature = "generator")] #[macro_use] extern crate serde_derive;
 #[cfg(feature = "generator")] #[macro_use] extern crate clap;
 #[cfg(feature = "generator")] #[macro_use] extern crate lazy_static;
+#[cfg(feature = "generator")] extern crate rand;
 #[cfg(feature = "serde_cbor")] extern crate serde_cbor as cbor;
 #[cfg(feature = "serde_yaml")] extern crate serde_yaml as yaml;
+#[cfg(feature = "serde_json")] extern crate serde_json as json;
+#[cfg(feature = "bincode")] extern crate bincode;
+#[cfg(feature = "rmp")] extern crate rmp_serde as rmp;
+#[cfg(feature = "toml")] extern crate toml;
+#[cfg(feature = "parallel")] extern crate rayon;
 
 mod prelude {
     #![cfg(feature = "generator")]
@@ -17,6 +24,18 @@ mod prelude {
             if cfg!(feature = "serde_yaml") {
                 extensions.push(("yaml", "YAML"));
             }
+            if cfg!(feature = "serde_json") {
+                extensions.push(("json", "JSON"));
+            }
+            if cfg!(feature = "bincode") {
+                extensions.push(("bincode", "bincode, a compact binary encoding"));
+            }
+            if cfg!(feature = "rmp") {
+                extensions.push(("msgpack", "MessagePack"));
+            }
+            if cfg!(feature = "toml") {
+                extensions.push(("toml", "TOML, for small chains edited by hand"));
+            }
             extensions
         };
 
@@ -36,7 +55,8 @@ These are the file formats and extensions supported:
         };
     }
 
-    #[cfg(any(feature = "serde_cbor", feature = "serde_yaml"))]
+    #[cfg(any(feature = "serde_cbor", feature = "serde_yaml", feature = "serde_json", feature = "bincode",
+              feature = "rmp", feature = "toml"))]
     mod serde_strategy {
         use markov_chain::{Chain, Chainable};
         use serde::{Serialize, Deserialize};
@@ -47,6 +67,14 @@ These are the file formats and extensions supported:
         use cbor;
         #[cfg(feature = "serde_yaml")]
         use yaml;
+        #[cfg(feature = "serde_json")]
+        use json;
+        #[cfg(feature = "bincode")]
+        use bincode;
+        #[cfg(feature = "rmp")]
+        use rmp;
+        #[cfg(feature = "toml")]
+        use toml;
 
         pub fn read_file(path: &str) -> io::Result<Vec<u8>> {
             let mut file = File::open(path)?;
@@ -56,15 +84,28 @@ These are the file formats and extensions supported:
         }
 
         pub fn write_file(path: &str, bytes: &[u8]) -> io::Result<()> {
-            let mut file = OpenOptions::new().create(true).write(true).open(path)?;
+            let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
             file.write_all(bytes)
         }
 
         type Result<T> = result::Result<T, String>;
 
+        /// Only the order of a serialized chain. Deserializing into this skips
+        /// over the chain data instead of building it.
+        #[cfg(any(feature = "serde_cbor", feature = "serde_yaml", feature = "serde_json", feature = "rmp",
+                  feature = "toml"))]
+        #[derive(Deserialize)]
+        struct ChainOrder {
+            order: usize,
+        }
+
         pub enum SerdeStrategy {
             CBOR,
             Yaml,
+            Json,
+            Bincode,
+            MessagePack,
+            Toml,
         }
 
         impl SerdeStrategy {
@@ -75,17 +116,57 @@ These are the file formats and extensions supported:
                 else if cfg!(feature = "serde_yaml") && path.ends_with(".yaml") {
                     Some(SerdeStrategy::Yaml)
                 }
+                else if cfg!(feature = "serde_json") && path.ends_with(".json") {
+                    Some(SerdeStrategy::Json)
+                }
+                else if cfg!(feature = "bincode") && path.ends_with(".bincode") {
+                    Some(SerdeStrategy::Bincode)
+                }
+                else if cfg!(feature = "rmp") && path.ends_with(".msgpack") {
+                    Some(SerdeStrategy::MessagePack)
+                }
+                else if cfg!(feature = "toml") && path.ends_with(".toml") {
+                    Some(SerdeStrategy::Toml)
+                }
                 else {
                     None
                 }
             }
 
+            pub fn name(&self) -> &'static str {
+                use self::SerdeStrategy::*;
+                match *self {
+                    CBOR => "CBOR",
+                    Yaml => "YAML",
+                    Json => "JSON",
+                    Bincode => "bincode",
+                    MessagePack => "MessagePack",
+                    Toml => "TOML",
+                }
+            }
+
+            pub fn peek_order(self, slice: &[u8]) -> Result<usize> {
+                use self::SerdeStrategy::*;
+                match self {
+                    CBOR => Self::order_from_cbor(slice),
+                    Yaml => Self::order_from_yaml(slice),
+                    Json => Self::order_from_json(slice),
+                    Bincode => Self::order_from_bincode(slice),
+                    MessagePack => Self::order_from_msgpack(slice),
+                    Toml => Self::order_from_toml(slice),
+                }
+            }
+
             pub fn into_vec<T>(self, chain: &Chain<T>) -> Result<Vec<u8>>
-                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                where for<'de> T: Chainable + Clone + Ord + Serialize + Deserialize<'de> {
                 use self::SerdeStrategy::*;
                 match self {
                     CBOR => Self::to_cbor(chain),
                     Yaml => Self::to_yaml(chain),
+                    Json => Self::to_json(chain),
+                    Bincode => Self::to_bincode(chain),
+                    MessagePack => Self::to_msgpack(chain),
+                    Toml => Self::to_toml(chain),
                 }
             }
 
@@ -95,6 +176,10 @@ These are the file formats and extensions supported:
                 match self {
                     CBOR => Self::from_cbor(slice),
                     Yaml => Self::from_yaml(slice),
+                    Json => Self::from_json(slice),
+                    Bincode => Self::from_bincode(slice),
+                    MessagePack => Self::from_msgpack(slice),
+                    Toml => Self::from_toml(slice),
                 }
             }
 
@@ -122,6 +207,16 @@ These are the file formats and extensions supported:
                 Err("cbor format is not supported".to_string())
             }
 
+            #[cfg(feature = "serde_cbor")]
+            pub fn order_from_cbor(slice: &[u8]) -> Result<usize> {
+                cbor::from_slice::<ChainOrder>(slice).map(|c| c.order).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "serde_cbor"))]
+            pub fn order_from_cbor(_: &[u8]) -> Result<usize> {
+                Err("cbor format is not supported".to_string())
+            }
+
             #[cfg(feature = "serde_yaml")]
             pub fn to_yaml<T>(chain: &Chain<T>) -> Result<Vec<u8>>
                 where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
@@ -146,11 +241,168 @@ These are the file formats and extensions supported:
                 where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
                 Err("yaml format is not supported".to_string())
             } 
+
+            #[cfg(feature = "serde_yaml")]
+            pub fn order_from_yaml(slice: &[u8]) -> Result<usize> {
+                use std::str;
+                let s = str::from_utf8(slice).map_err(|e| e.to_string())?;
+                yaml::from_str::<ChainOrder>(s).map(|c| c.order).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "serde_yaml"))]
+            pub fn order_from_yaml(_: &[u8]) -> Result<usize> {
+                Err("yaml format is not supported".to_string())
+            }
+
+            // JSON can't use serde directly on a chain, since it needs string keys
+            #[cfg(feature = "serde_json")]
+            pub fn to_json<T>(chain: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                chain.to_json().map(|c| c.into_bytes()).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "serde_json"))]
+            pub fn to_json<T>(_: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("json format is not supported".to_string())
+            }
+
+            #[cfg(feature = "serde_json")]
+            pub fn from_json<T>(slice: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                use std::str;
+                let s = str::from_utf8(slice).map_err(|e| e.to_string())?;
+                Chain::from_json(s).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "serde_json"))]
+            pub fn from_json<T>(_: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("json format is not supported".to_string())
+            }
+
+            #[cfg(feature = "serde_json")]
+            pub fn order_from_json(slice: &[u8]) -> Result<usize> {
+                json::from_slice::<ChainOrder>(slice).map(|c| c.order).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "serde_json"))]
+            pub fn order_from_json(_: &[u8]) -> Result<usize> {
+                Err("json format is not supported".to_string())
+            }
+
+            #[cfg(feature = "bincode")]
+            pub fn to_bincode<T>(chain: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                bincode::serialize(chain).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "bincode"))]
+            pub fn to_bincode<T>(_: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("bincode format is not supported".to_string())
+            }
+
+            #[cfg(feature = "bincode")]
+            pub fn from_bincode<T>(slice: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                bincode::deserialize(slice).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "bincode"))]
+            pub fn from_bincode<T>(_: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("bincode format is not supported".to_string())
+            }
+
+            // bincode isn't self-describing, so the chain data can't be
+            // skipped over to get to the order
+            #[cfg(feature = "bincode")]
+            pub fn order_from_bincode(slice: &[u8]) -> Result<usize> {
+                Self::from_bincode::<String>(slice).map(|c| c.order())
+            }
+
+            #[cfg(not(feature = "bincode"))]
+            pub fn order_from_bincode(_: &[u8]) -> Result<usize> {
+                Err("bincode format is not supported".to_string())
+            }
+
+            #[cfg(feature = "rmp")]
+            pub fn to_msgpack<T>(chain: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                chain.to_msgpack().map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "rmp"))]
+            pub fn to_msgpack<T>(_: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("msgpack format is not supported".to_string())
+            }
+
+            #[cfg(feature = "rmp")]
+            pub fn from_msgpack<T>(slice: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Chain::from_msgpack(slice).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "rmp"))]
+            pub fn from_msgpack<T>(_: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("msgpack format is not supported".to_string())
+            }
+
+            #[cfg(feature = "rmp")]
+            pub fn order_from_msgpack(slice: &[u8]) -> Result<usize> {
+                rmp::from_slice::<ChainOrder>(slice).map(|c| c.order).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "rmp"))]
+            pub fn order_from_msgpack(_: &[u8]) -> Result<usize> {
+                Err("msgpack format is not supported".to_string())
+            }
+
+            // TOML can't use serde directly on a chain either, for the same
+            // reason as JSON
+            #[cfg(feature = "toml")]
+            pub fn to_toml<T>(chain: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Ord + Serialize + Deserialize<'de> {
+                chain.to_toml().map(|c| c.into_bytes()).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "toml"))]
+            pub fn to_toml<T>(_: &Chain<T>) -> Result<Vec<u8>>
+                where for<'de> T: Chainable + Clone + Ord + Serialize + Deserialize<'de> {
+                Err("toml format is not supported".to_string())
+            }
+
+            #[cfg(feature = "toml")]
+            pub fn from_toml<T>(slice: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                use std::str;
+                let s = str::from_utf8(slice).map_err(|e| e.to_string())?;
+                Chain::from_toml(s).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "toml"))]
+            pub fn from_toml<T>(_: &[u8]) -> Result<Chain<T>>
+                where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+                Err("toml format is not supported".to_string())
+            }
+
+            #[cfg(feature = "toml")]
+            pub fn order_from_toml(slice: &[u8]) -> Result<usize> {
+                toml::from_slice::<ChainOrder>(slice).map(|c| c.order).map_err(|e| e.to_string())
+            }
+
+            #[cfg(not(feature = "toml"))]
+            pub fn order_from_toml(_: &[u8]) -> Result<usize> {
+                Err("toml format is not supported".to_string())
+            }
         }
 
 
         pub fn write_chain<T>(chain: &Chain<T>, path: &str) -> Result<()>
-            where for<'de> T: Chainable + Clone + Serialize + Deserialize<'de> {
+            where for<'de> T: Chainable + Clone + Ord + Serialize + Deserialize<'de> {
             if let Some(strat) = SerdeStrategy::from_path(path) {
                 let bytes: Vec<u8> = strat.into_vec(chain)?;
                 write_file(path, &bytes).map_err(|e| e.to_string())
@@ -175,28 +427,176 @@ These are the file formats and extensions supported:
         }
     }
 
-    #[cfg(any(feature = "serde_cbor", feature = "serde_yaml"))]
+    #[cfg(any(feature = "serde_cbor", feature = "serde_yaml", feature = "serde_json", feature = "bincode",
+              feature = "rmp", feature = "toml"))]
     use self::serde_strategy::*;
 
 
-    use markov_chain::Chain;
-    use std::io::{self, Write};
+    use markov_chain::{Chain, MergeError};
+    use rand::{SeedableRng, StdRng};
+    #[cfg(feature = "parallel")]
+    use rayon::prelude::*;
+    use std::collections::HashSet;
+    use std::fs::File;
+    use std::io::{self, BufReader, Read, Write};
     use std::process;
     use std::fmt::Display;
     use std::path::Path;
 
     macro_rules! exit_err {
         ($fmt:expr, $( $item:expr ),*) => {
-            exit_err(format!($fmt, $($item),*));
+            exit_err(format!($fmt, $($item),*))
         };
     } 
 
-    pub fn train(order: usize, update_files: Vec<&str>, input_files: Vec<&str>) {
+    /// Reads an input file, or standard input if the input is `-`.
+    fn read_input(input: &str) -> io::Result<Vec<u8>> {
+        if input == "-" {
+            let mut contents = Vec::new();
+            io::stdin().read_to_end(&mut contents)?;
+            Ok(contents)
+        }
+        else {
+            read_file(input)
+        }
+    }
+
+    /// Finds the order of the first markov chain file in a list of inputs,
+    /// defaulting to 1 when there are none.
+    fn detect_order(input_files: &[&str]) -> usize {
+        for input in input_files {
+            if let Some(strat) = SerdeStrategy::from_path(input) {
+                let bytes = match read_file(input) {
+                    Ok(b) => b,
+                    Err(e) => exit_err!("could not read {}: {}", input, e),
+                };
+                match strat.peek_order(&bytes) {
+                    Ok(order) => return order,
+                    Err(e) => exit_err!("could not read {}: {}", input, e),
+                }
+            }
+        }
+        1
+    }
+
+    /// Reads a markov chain file to be merged into a chain of the given order.
+    fn read_chain_of_order(order: usize, path: &str) -> Result<Chain<String>, String> {
+        let chain = read_chain(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        if chain.order() != order {
+            let e = MergeError::OrderMismatch { self_order: order, other_order: chain.order() };
+            return Err(format!("could not merge {}: {}", path, e));
+        }
+        Ok(chain)
+    }
+
+    /// Reads markov chain files of the given order and merges them together,
+    /// reading them in parallel if the `parallel` feature is on. Each chain is
+    /// merged in as soon as it's read, rather than holding every chain until
+    /// they have all been read.
+    #[cfg(feature = "parallel")]
+    fn read_merged(order: usize, paths: &[&str]) -> Result<Chain<String>, String> {
+        paths.par_iter()
+            .map(|path| read_chain_of_order(order, path))
+            .try_reduce(|| Chain::new(order), |mut chain, other| {
+                chain.try_merge(&other).map_err(|e| e.to_string())?;
+                Ok(chain)
+            })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn read_merged(order: usize, paths: &[&str]) -> Result<Chain<String>, String> {
+        paths.iter()
+            .try_fold(Chain::new(order), |mut chain, path| {
+                chain.try_merge(&read_chain_of_order(order, path)?).map_err(|e| e.to_string())?;
+                Ok(chain)
+            })
+    }
+
+    /// Builds a chain of the given order from a list of inputs, merging in
+    /// the markov chain files and training on everything else. Inputs are
+    /// taken in the order they're given, so text given after a chain file is
+    /// trained more recently than anything in it. Chain files given next to
+    /// each other are read together.
+    fn load_inputs(order: usize, input_files: Vec<&str>) -> Chain<String> {
+        let mut chain = Chain::<String>::new(order);
+        let mut chain_files = Vec::new();
+        for input in input_files {
+            if SerdeStrategy::from_path(input).is_some() {
+                chain_files.push(input);
+                continue;
+            }
+            merge_chain_files(&mut chain, &mut chain_files);
+            let contents = match read_input(input) {
+                Ok(c) => String::from_utf8(c).unwrap(),
+                Err(e) => exit_err!("could not read {}: {}", input, e),
+            };
+            chain.train_string(&contents);
+        }
+        merge_chain_files(&mut chain, &mut chain_files);
+        chain
+    }
+
+    /// Merges the chain files read so far into `chain`, leaving none to read.
+    fn merge_chain_files(chain: &mut Chain<String>, chain_files: &mut Vec<&str>) {
+        if chain_files.is_empty() {
+            return;
+        }
+        match read_merged(chain.order(), chain_files) {
+            Ok(merged) => chain.merge(&merged),
+            Err(e) => exit_err(e),
+        };
+        chain_files.clear();
+    }
+
+    pub fn info(input: &str) {
+        let strat = match SerdeStrategy::from_path(input) {
+            Some(s) => s,
+            None => exit_err!("unknown strategy for reading chain file `{}`", input),
+        };
+        let bytes = match read_file(input) {
+            Ok(b) => b,
+            Err(e) => exit_err!("could not read {}: {}", input, e),
+        };
+        let format = strat.name();
+        let order = match strat.peek_order(&bytes) {
+            Ok(o) => o,
+            Err(e) => exit_err!("could not read {}: {}", input, e),
+        };
+        println!("Format: {}", format);
+        println!("Order:  {}", order);
+    }
+
+    pub fn stats(input: &str) {
+        let chain: Chain<String> = match read_chain(input) {
+            Ok(c) => c,
+            Err(e) => exit_err!("could not read {}: {}", input, e),
+        };
+        println!("Order:        {}", chain.order());
+        println!("Nodes:        {}", chain.node_count());
+        println!("Edges:        {}", chain.edge_count());
+        println!("Observations: {}", chain.total_observations());
+        println!("Top starting tokens:");
+        for (token, weight) in chain.most_common_starts(10) {
+            println!("    {:>6}  {}", weight, token);
+        }
+    }
+
+    /// Removes the links of a chain below `min_weight`, if given, and reports
+    /// how many were removed.
+    fn prune(chain: &mut Chain<String>, min_weight: Option<u32>) {
+        if let Some(min_weight) = min_weight {
+            let edges = chain.edge_count();
+            chain.prune(min_weight);
+            println!("    {} edges pruned", edges - chain.edge_count());
+        }
+    }
+
+    pub fn train(order: usize, update_files: Vec<&str>, input_files: Vec<&str>, min_weight: Option<u32>) {
         let mut chains = Vec::new();
 
         // make sure all the input files exist
         for input in &input_files {
-            if !Path::new(input).exists() {
+            if *input != "-" && !Path::new(input).exists() {
                 exit_err!("could not find input file `{}`", input);
             }
         }
@@ -217,22 +617,36 @@ These are the file formats and extensions supported:
             }
         }
 
-        // read each input file
-        let mut inputs = Vec::new();
-        for input in &input_files {
-            let contents = match read_file(input) {
+        // standard input can only be read once, so it's kept for every chain;
+        // files are streamed instead, since they may be too large to hold
+        let stdin = if input_files.contains(&"-") {
+            match read_input("-") {
                 Ok(c) => String::from_utf8(c).unwrap(),
-                Err(e) => exit_err!("could not read `{}`: {}", input, e),
-            };
-            inputs.push(contents);
+                Err(e) => exit_err!("could not read `-`: {}", e),
+            }
         }
+        else {
+            String::new()
+        };
 
         // train and write
         for (path, mut chain) in chains {
             println!("Training {}", path);
-            for input in &inputs {
-                chain.train_string(input);
+            for input in &input_files {
+                let count = if *input == "-" {
+                    chain.train_string_counted(&stdin)
+                }
+                else {
+                    let trained = File::open(input)
+                        .and_then(|file| chain.train_reader_counted(BufReader::new(file)));
+                    match trained {
+                        Ok(count) => count,
+                        Err(e) => exit_err!("could not read `{}`: {}", input, e),
+                    }
+                };
+                println!("    {} tokens from {}", count, input);
             }
+            prune(&mut chain, min_weight);
 
             println!("Writing {}", path);
             if let Err(e) = write_chain(&chain, path) {
@@ -242,51 +656,68 @@ These are the file formats and extensions supported:
         }
     }
 
-    pub fn generate(order: usize, paragraphs: usize, sentences: usize, input_files: Vec<&str>) {
-        let mut chain = Chain::<String>::new(order);
-        for input in input_files {
-            if SerdeStrategy::from_path(input).is_some() {
-                let input_chain = match read_chain(input) {
-                    Ok(c) => c,
-                    Err(e) => exit_err!("could not read {}: {}", input, e),
+    /// Reads a list of allowed words from a file, one word per line.
+    fn read_allow_file(path: &str) -> HashSet<String> {
+        let contents = match read_file(path) {
+            Ok(c) => String::from_utf8(c).unwrap(),
+            Err(e) => exit_err!("could not read {}: {}", path, e),
+        };
+        contents.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Generates text from the inputs. Given a seed, the same seed and inputs
+    /// always generate the same text.
+    pub fn generate(order: Option<usize>, count: usize, paragraphs: usize, sentences: usize,
+                    input_files: Vec<&str>, allow_file: Option<&str>, seed: Option<usize>) -> String {
+        // punctuation is always allowed, so that sentences can end
+        let allowed = allow_file.map(read_allow_file)
+            .map(|allowed| move |w: &String| allowed.contains(w) || !w.chars().any(char::is_alphanumeric));
+        let order = order.unwrap_or_else(|| detect_order(&input_files));
+        let chain = load_inputs(order, input_files);
+        let mut outputs = Vec::new();
+        for i in 0 .. count {
+            // only a seeded RNG is passed on, since generation has to keep its
+            // choices in a fixed order for a seed to repeat its output. Each
+            // output gets its own, so that it doesn't depend on the ones
+            // before it
+            let mut rng = seed.map(|seed| StdRng::from_seed(&[seed, i][..]));
+            let mut pgs = Vec::new();
+            // generate paragraphs
+            for _ in 0 .. paragraphs {
+                let paragraph = match (allowed.as_ref(), rng.as_mut()) {
+                    (Some(allowed), Some(rng)) => chain.generate_paragraph_filtered_with_rng(rng, sentences, allowed),
+                    (Some(allowed), None) => chain.generate_paragraph_filtered(sentences, allowed),
+                    (None, Some(rng)) => chain.generate_paragraph_with_rng(rng, sentences),
+                    (None, None) => chain.generate_paragraph(sentences),
                 };
-                chain.merge(&input_chain);
+                pgs.push(paragraph);
             }
-            else {
-                let contents = match read_file(input) {
-                    Ok(c) => String::from_utf8(c).unwrap(),
-                    Err(e) => exit_err!("could not read {}: {}", input, e),
-                };
-                chain.train_string(&contents);
-            };
+            outputs.push(pgs.join("\n\n"));
         }
-        let mut pgs = Vec::new();
-        // generate paragraphs
-        for _ in 0 .. paragraphs {
-            pgs.push(chain.generate_paragraph(sentences));
-        }
-        println!("{}", pgs.join("\n\n"));
+        outputs.join("\n\n---\n\n")
     }
 
-    pub fn merge(order: usize, input_files: Vec<&str>, output_file: &str) {
-        let mut chain = Chain::<String>::new(order);
-        for input in input_files {
-            if SerdeStrategy::from_path(input).is_some() {
-                let input_chain = match read_chain(input) {
-                    Ok(c) => c,
-                    Err(e) => exit_err!("could not read {}: {}", input, e),
-                };
-                chain.merge(&input_chain);
-            }
-            else {
-                let contents = match read_file(input) {
-                    Ok(c) => String::from_utf8(c).unwrap(),
-                    Err(e) => exit_err!("could not read {}: {}", input, e),
-                };
-                chain.train_string(&contents);
-            };
+    /// Writes generated text to `output_file` if given, or prints it
+    /// otherwise. Either way, the text ends with a newline.
+    pub fn write_output(text: &str, output_file: Option<&str>) {
+        let text = format!("{}\n", text);
+        match output_file {
+            Some(path) => if let Err(e) = write_file(path, text.as_bytes()) {
+                exit_err!("could not write file {}: {}", path, e);
+            },
+            None => print!("{}", text),
         }
-        
+    }
+
+    pub fn merge(order: Option<usize>, input_files: Vec<&str>, output_file: &str, min_weight: Option<u32>) {
+        let order = order.unwrap_or_else(|| detect_order(&input_files));
+        let mut chain = load_inputs(order, input_files);
+        prune(&mut chain, min_weight);
+
         if let Err(e) = write_chain(&chain, output_file) {
             exit_err!("could not write file {}: {}", output_file, e);
         }
@@ -301,6 +732,19 @@ These are the file formats and extensions supported:
 
 #[cfg(feature = "generator")]
 use prelude::*;
+#[cfg(feature = "generator")]
+use clap::ArgMatches;
+
+/// Parses the `--min-weight` option of the `train` and `merge` commands.
+#[cfg(feature = "generator")]
+fn parse_min_weight(matches: &ArgMatches) -> Option<u32> {
+    match matches.value_of("MIN_WEIGHT")
+        .map(|x| x.parse::<u32>()) {
+            Some(Ok(n)) => Some(n),
+            Some(Err(e)) => exit_err(format!("invalid number for min weight: {}", e)),
+            None => None,
+        }
+}
 
 #[cfg(feature = "generator")]
 fn main() {
@@ -312,22 +756,36 @@ fn main() {
         (after_help: AVAILABLE_FORMATS.as_str())
         (@subcommand train =>
             (about: "Trains a new markov chain, or updates an existing markov chain from a file.")
-            (@arg INPUT: +required +multiple "Sets the input training data to use")
-            (@arg OUTPUT: -o --output +required +takes_value +multiple "Sets the list of files to update or create")
+            (@arg INPUT: +required +multiple "Sets the input training data to use, or - to read it from standard input")
+            (@arg OUTPUT: -o --output +required +takes_value +multiple number_of_values(1) "Sets a file to update or create; give it more than once to train several files")
             (@arg ORDER: -r --order +takes_value "Sets the order of the markov chain")
+            (@arg MIN_WEIGHT: --("min-weight") +takes_value "Removes links seen fewer than this many times before writing, to keep rare transitions from bloating the file")
         )
         (@subcommand generate =>
             (about: "Generates a string of text based on a file, or a saved markov chain in a supported format.")
-            (@arg INPUT: +required +multiple "Sets the input training data or markov chain file to use")
-            (@arg PARAGRAPHS: -p --paragraphs +takes_value "The number of paragraphs to generate")
+            (@arg INPUT: +required +multiple "Sets the input training data or markov chain file to use, or - to read training data from standard input")
+            (@arg COUNT: -c --count +takes_value "The number of separate outputs to generate, each made of --paragraphs paragraphs of --sentences sentences, and separated by a line of ---")
+            (@arg PARAGRAPHS: -p --paragraphs +takes_value "The number of paragraphs to generate per output")
             (@arg SENTENCES: -s --sentences +takes_value "The number of sentences to generate per paragraph")
-            (@arg ORDER: -r --order +takes_value "Sets the order of the markov chain")
+            (@arg ALLOW_FILE: --("allow-file") +takes_value "Sets a file listing the only words allowed in the output, one per line; punctuation is always allowed, and a sentence ends early when no allowed word can follow")
+            (@arg ORDER: -r --order +takes_value "Sets the order of the markov chain, defaulting to the order of the first chain file")
+            (@arg OUTPUT: -o --output +takes_value "Sets a file to write the generated text to, instead of printing it")
+            (@arg SEED: -S --seed +takes_value "Seeds the random number generator, so that the same seed and inputs always generate the same text")
         )
         (@subcommand merge =>
             (about: "Merges many markov chain files together into one file.")
-            (@arg INPUT: +required +multiple "Sets the input training data or markov chain file to use")
+            (@arg INPUT: +required +multiple "Sets the input training data or markov chain file to use, or - to read training data from standard input")
             (@arg OUTPUT: -o --out +required +takes_value "Sets the file where the final merged markov chain is saved.")
-            (@arg ORDER: -r --order +takes_value "Sets the order of the markov chain")
+            (@arg ORDER: -r --order +takes_value "Sets the order of the markov chain, defaulting to the order of the first chain file")
+            (@arg MIN_WEIGHT: --("min-weight") +takes_value "Removes links seen fewer than this many times before writing, to keep rare transitions from bloating the file")
+        )
+        (@subcommand stats =>
+            (about: "Prints the size of a saved markov chain file, and the tokens it most often starts with.")
+            (@arg INPUT: +required "Sets the markov chain file to inspect")
+        )
+        (@subcommand info =>
+            (about: "Prints the format and order of a saved markov chain file.")
+            (@arg INPUT: +required "Sets the markov chain file to inspect")
         )
     );
     
@@ -353,19 +811,26 @@ fn main() {
             let input_files = matches.values_of("INPUT")
                 .unwrap()
                 .collect();
-            train(order, update_files, input_files);
+            let min_weight = parse_min_weight(matches);
+            train(order, update_files, input_files, min_weight);
         },
         Some("generate") => {
             let matches = matches.subcommand_matches("generate").unwrap();
             let order = match matches.value_of("ORDER")
+                .map(|x| x.parse::<usize>()) {
+                    Some(Ok(n)) => Some(n),
+                    Some(Err(e)) => exit_err(format!("invalid number for order: {}", e)),
+                    None => None,
+                };
+            if order == Some(0) {
+                exit_err("order must be at least 1");
+            }
+            let count = match matches.value_of("COUNT")
                 .map(|x| x.parse::<usize>())
                 .unwrap_or(Ok(1)) {
                     Ok(n) => n,
-                    Err(e) => exit_err(format!("invalid number for order: {}", e)),
+                    Err(e) => exit_err(format!("invalid number for count: {}", e)),
                 };
-            if order == 0 {
-                exit_err("order must be at least 1");
-            }
             let paragraphs = match matches.value_of("PARAGRAPHS")
                 .map(|x| x.parse::<usize>())
                 .unwrap_or(Ok(1)) {
@@ -381,23 +846,45 @@ fn main() {
             let input_files = matches.values_of("INPUT")
                 .unwrap()
                 .collect();
-            generate(order, paragraphs, sentences, input_files);
+            let allow_file = matches.value_of("ALLOW_FILE");
+            let seed = match matches.value_of("SEED")
+                .map(|x| x.parse::<usize>()) {
+                    Some(Ok(n)) => Some(n),
+                    Some(Err(e)) => exit_err(format!("invalid number for seed: {}", e)),
+                    None => None,
+                };
+            let output_file = matches.value_of("OUTPUT");
+            let text = generate(order, count, paragraphs, sentences, input_files, allow_file, seed);
+            write_output(&text, output_file);
         },
         Some("merge") => {
             let matches = matches.subcommand_matches("merge").unwrap();
             let order = match matches.value_of("ORDER")
-                .map(|x| x.parse::<usize>())
-                .unwrap_or(Ok(1)) {
-                    Ok(n) => n,
-                    Err(e) => exit_err(format!("invalid number for order: {}", e)),
+                .map(|x| x.parse::<usize>()) {
+                    Some(Ok(n)) => Some(n),
+                    Some(Err(e)) => exit_err(format!("invalid number for order: {}", e)),
+                    None => None,
                 };
             let input_files = matches.values_of("INPUT")
                 .unwrap()
                 .collect();
             let output_file = matches.value_of("OUTPUT")
                 .unwrap();
-            merge(order, input_files, output_file);
-        }
+            let min_weight = parse_min_weight(matches);
+            merge(order, input_files, output_file, min_weight);
+        },
+        Some("stats") => {
+            let matches = matches.subcommand_matches("stats").unwrap();
+            let input = matches.value_of("INPUT")
+                .unwrap();
+            stats(input);
+        },
+        Some("info") => {
+            let matches = matches.subcommand_matches("info").unwrap();
+            let input = matches.value_of("INPUT")
+                .unwrap();
+            info(input);
+        },
         Some(command) => {
             helper.print_help().unwrap();
             println!();
@@ -43,19 +43,188 @@ extern crate regex;
 #[macro_use]
 extern crate lazy_static;
 
-use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
-use rand::Rng;
+#[cfg(feature = "aatxe")]
+extern crate markov;
+#[cfg(feature = "bincode")]
+extern crate bincode;
+#[cfg(feature = "rmp")]
+extern crate rmp_serde;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "serde_cbor")]
+extern crate serde_cbor;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(feature = "serde_yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "toml")]
+extern crate toml;
+
+use rand::{Rng, ThreadRng};
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use regex::Regex;
-use std::collections::HashMap;
-use std::hash::Hash;
+use serde::ser::SerializeStruct;
+use std::cell::Cell;
+use std::cmp;
+use std::error;
+use std::fmt;
+#[cfg(feature = "aatxe")]
+use std::mem;
+use std::fs;
+use std::io;
+#[cfg(any(feature = "serde_cbor", feature = "serde_json", feature = "toml"))]
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::{self, FromIterator};
+use std::marker::PhantomData;
+use std::ops::{Add, AddAssign};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::vec;
 
 // Stolen from public domain project https://github.com/aatxe/markov
 /// A trait that defines a restrictions required for chainable items.
 pub trait Chainable: Eq + Hash {}
 impl<T> Chainable for T where T: Eq + Hash {}
 
+/// A source of random numbers for generation.
+///
+/// Generation only needs a stream of random `u32`s, so this can be implemented
+/// on top of whatever is available on the target, like `getrandom` or a
+/// JavaScript RNG under WASM, without depending on the `rand` API. Every
+/// `rand::Rng` is a `RandomSource`.
+pub trait RandomSource {
+    /// Gets the next random `u32`.
+    fn next_u32(&mut self) -> u32;
+
+    /// Gets whether this source can be seeded to repeat its numbers, which is
+    /// the default. Generating with a repeatable source chooses between
+    /// candidates in a fixed order rather than the order of a `HashMap`,
+    /// which differs from run to run, so that the same chain and seed always
+    /// generate the same output. This costs a sort at every step, which an
+    /// unseeded source can skip by returning `false`.
+    fn is_repeatable(&self) -> bool {
+        true
+    }
+}
+
+impl<R> RandomSource for R where R: Rng {
+    fn next_u32(&mut self) -> u32 {
+        Rng::next_u32(self)
+    }
+}
+
+/// The thread-local RNG, used by the methods that don't take one. It isn't
+/// seeded, so generating with it doesn't need to order candidates.
+struct Unseeded(ThreadRng);
+
+impl RandomSource for Unseeded {
+    fn next_u32(&mut self) -> u32 {
+        Rng::next_u32(&mut self.0)
+    }
+
+    fn is_repeatable(&self) -> bool {
+        false
+    }
+}
+
+/// Gets the RNG for generating without a seed.
+fn unseeded() -> Unseeded {
+    Unseeded(rand::thread_rng())
+}
+
+/// A type that can be used for the weights of the links in a chain.
+///
+/// This is implemented for `u32`, the default, as well as `u64` for chains
+/// trained on corpora large enough to overflow a `u32`, and `f64` for
+/// fractional weights. Integer weights saturate instead of overflowing.
+pub trait Weight: Copy + PartialOrd {
+    /// Gets a weight of zero.
+    fn zero() -> Self;
+    /// Gets a weight of one, which is the weight of a single observation.
+    fn one() -> Self;
+    /// Adds two weights, saturating at the largest weight.
+    fn saturating_add(self, other: Self) -> Self;
+    /// Subtracts a weight from another, saturating at zero.
+    fn saturating_sub(self, other: Self) -> Self;
+    /// Converts this weight to an `f64`.
+    fn to_f64(self) -> f64;
+    /// Converts an `f64` to a weight. Integer weights are rounded to the
+    /// nearest whole number and clamped to the range of the type.
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! impl_integer_weight {
+    ($($weight:ty),*) => {$(
+        impl Weight for $weight {
+            fn zero() -> Self {
+                0
+            }
+
+            fn one() -> Self {
+                1
+            }
+
+            fn saturating_add(self, other: Self) -> Self {
+                <$weight>::saturating_add(self, other)
+            }
+
+            fn saturating_sub(self, other: Self) -> Self {
+                <$weight>::saturating_sub(self, other)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                // float to integer casts saturate, and NaN becomes 0
+                value.round() as $weight
+            }
+        }
+    )*};
+}
+
+impl_integer_weight!(u32, u64);
+
+impl Weight for f64 {
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn one() -> Self {
+        1.0
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        (self - other).max(0.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
 type Node<T> = Vec<Option<T>>;
-type Link<T> = HashMap<Option<T>, u32>;
+type Link<T, W = u32, H = RandomState> = HashMap<Option<T>, W, H>;
+
+/// The error for a chain with an order of 0, which can't be trained.
+const ZERO_ORDER: &str = "the order of a markov chain must be at least 1";
+/// The error for a chain with a node that doesn't match its order, which
+/// would panic when generating from that node.
+const NODE_LENGTH: &str = "every node of a markov chain must be as long as its order";
 
 // don't add where T: Serialize + DeserializeOwned, see
 // https://github.com/serde-rs/serde/issues/890
@@ -63,7 +232,8 @@ type Link<T> = HashMap<Option<T>, u32>;
 ///
 /// A markov chain has an order, which determines how many items
 /// per node are held. The chain itself is a map of vectors, which point to
-/// a map of single elements pointing at a weight.
+/// a map of single elements pointing at a weight. Weights are `u32` by
+/// default, and can be any other `Weight` type; see `with_weights`.
 ///
 /// ```
 /// use markov_chain::Chain;
@@ -78,390 +248,6902 @@ type Link<T> = HashMap<Option<T>, u32>;
 /// let sequence = chain.generate();
 /// println!("{:?} ", sequence);
 /// ```
+///
+/// The maps use the standard library's `RandomState` hasher by default. Any
+/// other `BuildHasher` can be used instead, which can speed up training on
+/// large inputs:
+///
+/// ```
+/// use markov_chain::Chain;
+/// use std::collections::hash_map::DefaultHasher;
+/// use std::hash::BuildHasherDefault;
+///
+/// type FixedChain<T> = Chain<T, u32, BuildHasherDefault<DefaultHasher>>;
+///
+/// let mut chain = FixedChain::with_weights(1);
+/// chain.train(vec![1, 2, 3]);
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "ChainData<T, W, H>",
+        bound(deserialize = "T: Clone + Chainable + serde::Deserialize<'de>, W: Weight + serde::Deserialize<'de>, \
+                             H: BuildHasher + Clone + Default"))]
+pub struct Chain<T, W = u32, H = RandomState> where T: Clone + Chainable, W: Weight, H: BuildHasher {
+    chain: HashMap<Node<T>, Link<T, W, H>, H>,
+    order: usize,
+    /// The opening node of each trained sequence, along with the value of
+    /// `clock` when it was last trained.
+    recency: HashMap<Node<T>, u64>,
+    /// The number of sequences that have been trained.
+    clock: u64,
+    /// The words that end a sentence, used by the string functions.
+    break_tokens: HashSet<String>,
+    /// Whether generated sentences start with a capital letter, used by the
+    /// string functions. This is a setting for generation rather than part of
+    /// what was trained, so it isn't serialized.
+    capitalize_sentences: bool,
+    /// Whether words are lowercased when training on strings. Like
+    /// `capitalize_sentences`, this is a setting rather than part of what was
+    /// trained, so it isn't serialized.
+    lowercase: bool,
+    /// Whether generation backs off to `unigram` at nodes with no links to
+    /// follow. Like `capitalize_sentences`, this is a setting for generation,
+    /// so it isn't serialized.
+    unigram_backoff: bool,
+    /// Every complete node (one without `None` padding) in the chain, so that
+    /// a random starting node can be chosen in constant time. This is kept up
+    /// to date as nodes are added, and rebuilt when nodes are removed.
+    starts: Vec<Node<T>>,
+    /// How often each item was trained, for backing off at dead ends. This
+    /// is only built the first time it's needed, and thrown away whenever the
+    /// chain changes.
+    unigram: OnceLock<Unigram<T>>,
+}
+
+/// The serialized form of a chain, which is turned into a `Chain` by
+/// rebuilding the parts of it that aren't serialized. A chain writes its own
+/// maps in this same layout when it's serialized.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Chainable + serde::Deserialize<'de>, W: serde::Deserialize<'de>, \
+                             H: BuildHasher + Default"))]
+struct ChainData<T, W, H> where T: Chainable, H: BuildHasher {
+    chain: HashMap<Node<T>, Link<T, W, H>, H>,
+    order: usize,
+    #[serde(default)]
+    recency: HashMap<Node<T>, u64>,
+    #[serde(default)]
+    clock: u64,
+    /// The break tokens, or `None` for the default ones.
+    #[serde(default)]
+    break_tokens: Option<HashSet<String>>,
+}
+
+/// The serialized parts of a chain, borrowed with every map and set sorted by
+/// key, so that equal chains are always written out the same way.
+#[cfg(any(feature = "serde_cbor", feature = "serde_json", feature = "toml"))]
+#[derive(Serialize)]
+struct SortedChainData<'a, T: 'a, W: 'a> {
+    chain: BTreeMap<&'a Node<T>, BTreeMap<&'a Option<T>, &'a W>>,
+    order: usize,
+    recency: BTreeMap<&'a Node<T>, &'a u64>,
+    clock: u64,
+    break_tokens: Option<BTreeSet<&'a String>>,
+}
+
+#[cfg(any(feature = "serde_cbor", feature = "serde_json", feature = "toml"))]
+impl<'a, T, W> SortedChainData<'a, T, W> where T: Clone + Chainable + Ord, W: Weight {
+    fn new<H>(chain: &'a Chain<T, W, H>) -> Self where H: BuildHasher {
+        SortedChainData {
+            chain: chain.chain.iter()
+                .map(|(node, link)| (node, link.iter().collect()))
+                .collect(),
+            order: chain.order,
+            recency: chain.recency.iter().collect(),
+            clock: chain.clock,
+            break_tokens: chain.custom_break_tokens().map(|tokens| tokens.iter().collect()),
+        }
+    }
+}
+
+/// A node's links as a list of pairs.
+#[cfg(any(feature = "serde_json", feature = "toml"))]
+type LinkList<T, W> = Vec<(Option<T>, W)>;
+
+/// The serialized parts of a chain with lists of pairs in place of maps, for
+/// formats whose maps can only have string keys.
+#[cfg(any(feature = "serde_json", feature = "toml"))]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, W: serde::Deserialize<'de>"))]
+struct ListChainData<T, W> {
+    chain: Vec<(Node<T>, LinkList<T, W>)>,
+    order: usize,
+    #[serde(default)]
+    recency: Vec<(Node<T>, u64)>,
+    #[serde(default)]
+    clock: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    break_tokens: Option<BTreeSet<String>>,
+}
+
+#[cfg(any(feature = "serde_json", feature = "toml"))]
+impl<T, W> ListChainData<T, W> where T: Clone + Chainable, W: Weight {
+    /// Lists the nodes, links and openings of a chain in the order they're
+    /// given.
+    #[cfg(feature = "serde_json")]
+    fn new<'a, C, L, R, B>(order: usize, clock: u64, chain: C, recency: R, break_tokens: Option<B>) -> Self
+        where C: IntoIterator<Item = (&'a Node<T>, L)>,
+              L: IntoIterator<Item = (&'a Option<T>, &'a W)>,
+              R: IntoIterator<Item = (&'a Node<T>, &'a u64)>,
+              B: IntoIterator<Item = &'a String>,
+              T: 'a, W: 'a {
+        ListChainData {
+            chain: chain.into_iter()
+                .map(|(node, link)| (node.clone(), link.into_iter().map(|(next, &weight)| (next.clone(), weight)).collect()))
+                .collect(),
+            order,
+            recency: recency.into_iter().map(|(node, &time)| (node.clone(), time)).collect(),
+            clock,
+            break_tokens: break_tokens.map(|tokens| tokens.into_iter().cloned().collect()),
+        }
+    }
+
+    /// Gathers the lists of pairs back into maps. A node or link that is
+    /// listed more than once has its weights added up, and the latest time is
+    /// kept for an opening listed more than once.
+    fn into_data<H>(self) -> ChainData<T, W, H> where H: BuildHasher + Default {
+        let mut chain: HashMap<Node<T>, Link<T, W, H>, H> = HashMap::default();
+        for (node, link) in self.chain {
+            let links = chain.entry(node).or_default();
+            for (next, weight) in link {
+                let total = links.entry(next).or_insert_with(W::zero);
+                *total = total.saturating_add(weight);
+            }
+        }
+        let mut recency = HashMap::new();
+        for (node, time) in self.recency {
+            let latest = recency.entry(node).or_insert(time);
+            *latest = cmp::max(*latest, time);
+        }
+        ChainData {
+            chain,
+            order: self.order,
+            recency,
+            clock: self.clock,
+            break_tokens: self.break_tokens.map(|tokens| tokens.into_iter().collect()),
+        }
+    }
+}
+
+/// Chains are serialized as a `ChainData`, which leaves out their settings
+/// and the caches that are rebuilt from the rest. Default break tokens are
+/// written as nothing, so that they're kept out of chains that aren't of
+/// strings; they're still written as a field, since formats like bincode
+/// can't leave fields out.
+impl<T, W, H> serde::Serialize for Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize, W: Weight + serde::Serialize, H: BuildHasher {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        let mut data = serializer.serialize_struct("ChainData", 5)?;
+        data.serialize_field("chain", &self.chain)?;
+        data.serialize_field("order", &self.order)?;
+        data.serialize_field("recency", &self.recency)?;
+        data.serialize_field("clock", &self.clock)?;
+        data.serialize_field("break_tokens", &self.custom_break_tokens())?;
+        data.end()
+    }
+}
+
+impl<T, W, H> Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher {
+    /// Gets the break tokens to serialize, which are `None` when they're
+    /// the default ones.
+    fn custom_break_tokens(&self) -> Option<&HashSet<String>> {
+        if self.break_tokens == *BREAK {
+            None
+        }
+        else {
+            Some(&self.break_tokens)
+        }
+    }
+}
+
+impl<T, W, H> TryFrom<ChainData<T, W, H>> for Chain<T, W, H>
+    where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    type Error = ChainError;
+
+    fn try_from(data: ChainData<T, W, H>) -> Result<Self, Self::Error> {
+        if data.order == 0 {
+            return Err(ChainError::ZeroOrder);
+        }
+        if data.chain.keys().chain(data.recency.keys()).any(|node| node.len() != data.order) {
+            return Err(ChainError::NodeLength);
+        }
+        let mut chain = Chain {
+            chain: data.chain,
+            order: data.order,
+            recency: data.recency,
+            clock: data.clock,
+            break_tokens: data.break_tokens.unwrap_or_else(default_break_tokens),
+            capitalize_sentences: false,
+            lowercase: false,
+            unigram_backoff: false,
+            starts: Vec::new(),
+            unigram: OnceLock::new(),
+        };
+        chain.rebuild_caches();
+        Ok(chain)
+    }
+}
+
+/// Two chains are equal when they have the same order and the same links.
+/// Training recency is not taken into account.
+impl<T, W, H> PartialEq for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn eq(&self, other: &Self) -> bool {
+        self.order == other.order && self.chain == other.chain
+    }
+}
+
+/// The per-link weight changes between two chains of the same order, made by
+/// `Chain::diff` and applied with `Chain::apply_delta`.
+///
+/// A delta can be serialized, so that a chain can be kept in sync by sending
+/// only what changed since the last update instead of the whole chain.
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct Chain<T> where T: Clone + Chainable {
-    chain: HashMap<Node<T>, Link<T>>,
+pub struct ChainDelta<T, W = u32> where T: Clone + Chainable, W: Weight {
+    /// The amount added to the weight of each link that got heavier.
+    added: HashMap<Node<T>, Link<T, W>>,
+    /// The amount taken from the weight of each link that got lighter.
+    removed: HashMap<Node<T>, Link<T, W>>,
+    order: usize,
+}
+
+impl<T, W> ChainDelta<T, W> where T: Clone + Chainable, W: Weight {
+    /// Gets the order of the chains this delta applies to.
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Gets whether this delta makes no changes.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A builder for a chain with settings other than the defaults, made by
+/// `Chain::builder`. Settings that aren't given keep the defaults of
+/// `Chain::new`, and the order defaults to 1.
+///
+/// The builder makes chains with the default weights and hasher. For others,
+/// name them in the type, like `ChainBuilder::<String, f64>::new()`.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let mut chain = Chain::builder()
+///     .order(2)
+///     .break_tokens(vec![String::from("."), String::from(";")])
+///     .lowercase(true)
+///     .capitalize_sentences(true)
+///     .build();
+/// chain.train_string("THE CAT SAT;");
+/// assert_eq!(chain.order(), 2);
+/// assert_eq!(chain.generate_sentence(), "The cat sat;");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChainBuilder<T, W = u32, H = RandomState> {
     order: usize,
+    break_tokens: HashSet<String>,
+    lowercase: bool,
+    capitalize_sentences: bool,
+    unigram_backoff: bool,
+    marker: PhantomData<(T, W, H)>,
+}
+
+impl<T, W, H> ChainBuilder<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    /// Creates a builder with the default settings.
+    pub fn new() -> Self {
+        ChainBuilder {
+            order: 1,
+            break_tokens: default_break_tokens(),
+            lowercase: false,
+            capitalize_sentences: false,
+            unigram_backoff: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Sets the order of the chain, which should be at least 1; see
+    /// `Chain::new`.
+    pub fn order(&mut self, order: usize) -> &mut Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets the words that end a sentence. See `Chain::set_break_tokens`.
+    pub fn break_tokens(&mut self, tokens: Vec<String>) -> &mut Self {
+        self.break_tokens = tokens.into_iter().collect();
+        self
+    }
+
+    /// Sets whether words are lowercased when training on strings. See
+    /// `Chain::set_lowercase`.
+    pub fn lowercase(&mut self, lowercase: bool) -> &mut Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Sets whether generated sentences are capitalized. See
+    /// `Chain::set_capitalize_sentences`.
+    pub fn capitalize_sentences(&mut self, capitalize: bool) -> &mut Self {
+        self.capitalize_sentences = capitalize;
+        self
+    }
+
+    /// Sets whether generation backs off at dead ends. See
+    /// `Chain::with_unigram_backoff`.
+    pub fn unigram_backoff(&mut self, backoff: bool) -> &mut Self {
+        self.unigram_backoff = backoff;
+        self
+    }
+
+    /// Builds an empty chain with these settings.
+    pub fn build(&self) -> Chain<T, W, H> {
+        let mut chain = Chain::with_weights(self.order);
+        chain.break_tokens = self.break_tokens.clone();
+        chain.lowercase = self.lowercase;
+        chain.capitalize_sentences = self.capitalize_sentences;
+        chain.unigram_backoff = self.unigram_backoff;
+        chain
+    }
+}
+
+impl<T, W, H> Default for ChainBuilder<T, W, H>
+    where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn default() -> Self {
+        ChainBuilder::new()
+    }
+}
+
+/// The reason a generated sequence came to an end.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Termination {
+    /// A terminal link was chosen, ending the sequence naturally.
+    Terminal,
+    /// The current node had no outgoing links, or the chain was empty.
+    DeadEnd,
+    /// The maximum number of items was reached.
+    Limit,
+}
+
+/// An error from combining markov chains.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MergeError {
+    /// The chains being combined have different orders.
+    OrderMismatch {
+        /// The order of the chain being merged into.
+        self_order: usize,
+        /// The order of the chain that didn't match it.
+        other_order: usize,
+    },
+    /// There were no chains to combine.
+    Empty,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::OrderMismatch { self_order, other_order } =>
+                write!(f, "cannot merge a chain of order {} with a chain of order {}", self_order, other_order),
+            MergeError::Empty => write!(f, "no chains to merge"),
+        }
+    }
+}
+
+impl error::Error for MergeError {}
+
+/// An error from making a chain out of parts that don't fit together, like a
+/// deserialized chain or a map given to `Chain::from_raw`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChainError {
+    /// The order is 0, which can't be trained.
+    ZeroOrder,
+    /// A node isn't as long as the order, which would panic when generating
+    /// from it.
+    NodeLength,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainError::ZeroOrder => f.write_str(ZERO_ORDER),
+            ChainError::NodeLength => f.write_str(NODE_LENGTH),
+        }
+    }
 }
 
+impl error::Error for ChainError {}
+
 impl<T> Chain<T> where T: Clone + Chainable {
-    /// Initializes a new markov chain with a given order.
+    /// Initializes a new markov chain with a given order, which should be at
+    /// least 1.
+    ///
+    /// A chain of order 0 has no room for even one item in a node, so it can't
+    /// learn anything: training it does nothing, and it never generates
+    /// anything. Use `try_new` to reject an order of 0 instead.
     /// # Examples
     /// ```
     /// use markov_chain::Chain;
     /// let chain: Chain<u32> = Chain::new(1);
     /// ```
     pub fn new(order: usize) -> Self {
+        Chain::with_capacity(order, 0)
+    }
+
+    /// Initializes a new markov chain like `new`, with room for at least
+    /// `nodes` nodes before it needs to grow. This saves rehashing the chain
+    /// over and over while training on a large corpus.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::with_capacity(1, 1000);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert_eq!(chain.node_count(), 4);
+    /// ```
+    pub fn with_capacity(order: usize, nodes: usize) -> Self {
+        let mut chain = Chain::with_weights(order);
+        chain.chain.reserve(nodes);
+        chain
+    }
+
+    /// Initializes a new markov chain with a given order like `new`, giving
+    /// `None` if the order is 0.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// assert!(Chain::<u32>::try_new(0).is_none());
+    /// assert_eq!(Chain::<u32>::try_new(2).unwrap().order(), 2);
+    /// ```
+    pub fn try_new(order: usize) -> Option<Self> {
+        if order == 0 {
+            return None;
+        }
+        Some(Chain::new(order))
+    }
+
+    /// Starts building a chain with settings other than the defaults. See
+    /// `ChainBuilder`.
+    pub fn builder() -> ChainBuilder<T> {
+        ChainBuilder::new()
+    }
+}
+
+impl<T, W, H> Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    /// Initializes a new markov chain with a given order, with weights of
+    /// type `W` and maps using the hasher `H`. `new` is the same for the
+    /// default `u32` weights and hasher. See `new` for chains of order 0.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::<u32, f64>::with_weights(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// ```
+    pub fn with_weights(order: usize) -> Self {
         Chain {
-            chain: HashMap::new(),
+            chain: HashMap::default(),
             order,
+            recency: HashMap::new(),
+            clock: 0,
+            break_tokens: default_break_tokens(),
+            capitalize_sentences: false,
+            lowercase: false,
+            unigram_backoff: false,
+            starts: Vec::new(),
+            unigram: OnceLock::new(),
         }
     } 
 
-    /// Gets the order of the markov chain. This is static from chain to chain.
+    /// Creates an empty chain of the given order, with the same settings as
+    /// this one: its break tokens, whether it capitalizes sentences and
+    /// lowercases words, and whether it backs off at dead ends.
+    fn empty_like(&self, order: usize) -> Self {
+        let mut chain = Chain::with_weights(order);
+        chain.break_tokens = self.break_tokens.clone();
+        chain.capitalize_sentences = self.capitalize_sentences;
+        chain.lowercase = self.lowercase;
+        chain.unigram_backoff = self.unigram_backoff;
+        chain
+    }
+
+    /// Gets the order of the markov chain. This is static from chain to chain:
+    /// every node of a chain is exactly as long as its order, so the order is
+    /// fixed when the chain is made. See `rebuild_at_order` and
+    /// `retrain_order` for getting a chain of another order.
     pub fn order(&self) -> usize {
         self.order
     }
 
+    /// Gets whether generation backs off at dead ends. See
+    /// `with_unigram_backoff`.
+    pub fn unigram_backoff(&self) -> bool {
+        self.unigram_backoff
+    }
+
+    /// Returns this chain with backoff at dead ends turned on or off.
+    ///
+    /// Normally, generation stops at a node with no links to follow, which
+    /// can happen well before a natural end after pruning or filtering. With
+    /// backoff, an item is chosen instead by how often each item was trained,
+    /// ignoring the node, and generation carries on from there. Only items
+    /// that lead to a node in the chain are chosen, so backing off never leads
+    /// straight into another dead end; if there are none, generation stops as
+    /// usual. Chains don't back off by default.
+    ///
+    /// This trades coherence for length: the item that is chosen has little
+    /// to do with what came before it. Nodes that have links are not
+    /// affected, so generation still ends on terminal links as usual.
+    ///
+    /// This only affects generation, so it isn't saved when the chain is
+    /// serialized; set it again after loading a chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// // nothing is known to follow 9
+    /// assert_eq!(chain.generate_from(&[9], -1), vec![9]);
+    /// let chain = chain.with_unigram_backoff(true);
+    /// assert!(chain.generate_from(&[9], -1).len() > 1);
+    /// ```
+    pub fn with_unigram_backoff(mut self, backoff: bool) -> Self {
+        self.set_unigram_backoff(backoff);
+        self
+    }
+
+    /// Sets whether generation backs off at dead ends, like
+    /// `with_unigram_backoff`.
+    pub fn set_unigram_backoff(&mut self, backoff: bool) -> &mut Self {
+        self.unigram_backoff = backoff;
+        self
+    }
+
     /// Gets whether the internal markov chain is empty.
     pub fn is_empty(&self) -> bool {
         self.chain.is_empty()
     }
 
-    /// Gets the raw underlying chain data structure.
-    pub fn chain(&self) -> &HashMap<Node<T>, Link<T>> {
-        &self.chain
+    /// Gets the number of nodes in the chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1]);
+    /// // the start, 1 and 2
+    /// assert_eq!(chain.node_count(), 3);
+    /// ```
+    pub fn node_count(&self) -> usize {
+        self.chain.len()
     }
 
-    /// Trains a sentence on a string of items.
+    /// Gets the number of links between nodes in the chain, including links
+    /// that end a sequence.
     /// # Examples
     /// ```
     /// use markov_chain::Chain;
     /// let mut chain = Chain::new(1);
-    /// let data = vec![10, 15, 20];
-    /// chain.train(data)
-    ///     .train(vec![]);
+    /// chain.train(vec![1, 2, 1]);
+    /// // start -> 1, 1 -> 2, 2 -> 1 and 1 -> end
+    /// assert_eq!(chain.edge_count(), 4);
     /// ```
-    pub fn train(&mut self, string: Vec<T>) -> &mut Self {
-        if string.is_empty() {
-            return self;
-        }
+    pub fn edge_count(&self) -> usize {
+        self.chain.values()
+            .map(|link| link.len())
+            .sum()
+    }
 
-        let order = self.order;
+    /// Gets the sum of every link weight in the chain. Each trained string
+    /// adds one for each of its items plus one for its end, so this measures
+    /// how much training the chain has seen. Fractional weights are summed
+    /// and then rounded.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1]);
+    /// assert_eq!(chain.total_observations(), 4);
+    /// ```
+    pub fn total_observations(&self) -> u64 {
+        self.chain.values()
+            .flat_map(|link| link.values())
+            .map(|weight| weight.to_f64())
+            .sum::<f64>()
+            .round() as u64
+    }
 
-        let mut string = string.into_iter()
-            .map(|x| Some(x))
-            .collect::<Vec<Option<T>>>();
-        while string.len() < order {
-            string.push(None);
-        }
+    /// Gets the raw underlying chain data structure.
+    pub fn chain(&self) -> &HashMap<Node<T>, Link<T, W, H>, H> {
+        &self.chain
+    }
 
-        let mut window = vec!(None; order);
-        self.update_link(&window, &string[0]);
+    /// Takes the raw underlying chain data structure out of the chain, without
+    /// cloning it. See `from_raw` to turn it back into a chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2]);
+    /// let map = chain.into_chain();
+    /// assert_eq!(map[&vec![Some(1)]][&Some(2)], 1);
+    /// ```
+    pub fn into_chain(self) -> HashMap<Node<T>, Link<T, W, H>, H> {
+        self.chain
+    }
 
-        let mut end = 0;
-        while end < string.len() - 1 {
-            window.remove(0);
-            let next = &string[end + 1];
-            window.push(string[end].clone());
+    /// Creates a chain of the given order from a raw chain data structure,
+    /// like the one `into_chain` gives. The chain has the default settings
+    /// and no training history, so `generate_recent` has no openings to
+    /// choose from.
+    ///
+    /// This fails with a `ChainError` if the order is 0 or any node isn't
+    /// exactly `order` items long, since generating from such a chain would
+    /// panic.
+    /// # Examples
+    /// ```
+    /// use markov_chain::{Chain, ChainError};
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2]);
+    /// let mut map = chain.into_chain();
+    /// map.get_mut(&vec![Some(1)]).unwrap().insert(Some(3), 4);
+    /// let chain = Chain::from_raw(map, 1).unwrap();
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(3)), 4);
+    ///
+    /// let mut map = chain.into_chain();
+    /// map.insert(vec![Some(1), Some(2)], Default::default());
+    /// assert_eq!(Chain::from_raw(map, 1), Err(ChainError::NodeLength));
+    /// ```
+    pub fn from_raw(chain: HashMap<Node<T>, Link<T, W, H>, H>, order: usize) -> Result<Self, ChainError> {
+        Chain::try_from(ChainData {
+            chain,
+            order,
+            recency: HashMap::new(),
+            clock: 0,
+            break_tokens: None,
+        })
+    }
 
-            self.update_link(&window, &next);
+    /// Iterates over every node in the chain, in no particular order.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1]);
+    /// assert_eq!(chain.nodes().count(), 3);
+    /// assert!(chain.nodes().any(|node| node == &[Some(2)]));
+    /// ```
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<T>> {
+        self.chain.keys()
+    }
 
-            end += 1;
-        }
-        window.remove(0);
-        window.push(string[end].clone());
-        self.update_link(&window, &None);
-        self
+    /// Iterates over the nodes that sequences begin from, in no particular
+    /// order. These are the nodes that begin with `None` padding, which
+    /// stands for the start of a sequence. Sequences shorter than the order
+    /// are also padded with `None` at the end, but those nodes are not
+    /// starting nodes.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// chain.train(vec![1, 2, 3]);
+    /// let mut starting = chain.starting_nodes().collect::<Vec<_>>();
+    /// starting.sort();
+    /// assert_eq!(starting, vec![&vec![None, None], &vec![None, Some(1)]]);
+    /// ```
+    pub fn starting_nodes(&self) -> impl Iterator<Item = &Node<T>> {
+        self.chain.keys()
+            .filter(|node| node.first() == Some(&None))
     }
 
-    /// Merges this markov chain with another.
+    /// Gets the links out of a node, mapping each item that can follow it to
+    /// its weight, with `None` standing for the end of a sequence.
     /// # Examples
     /// ```
     /// use markov_chain::Chain;
-    /// let mut chain1 = Chain::new(1);
-    /// let mut chain2 = chain1.clone();
-    /// chain1.train(vec![1, 2, 3]);
-    /// chain2.train(vec![2, 3, 4, 5, 6])
-    ///     .merge(&chain1);
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3]);
+    /// let links = chain.links(&[Some(1)]).unwrap();
+    /// assert_eq!(links.len(), 2);
+    /// assert!(chain.links(&[Some(4)]).is_none());
+    /// ```
+    pub fn links(&self, node: &[Option<T>]) -> Option<&Link<T, W, H>> {
+        self.chain.get(node)
+    }
+
+    /// Gets the links out of a node like `links`, as a list sorted from the
+    /// heaviest link to the lightest. Ties go to the smallest item, with the
+    /// end of a sequence first, so the order never depends on how links happen
+    /// to be stored. This is empty if the node is not in the chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3, 1, 2]);
+    /// assert_eq!(chain.links_sorted(&[Some(1)]), vec![(&Some(2), 2), (&Some(3), 1)]);
+    /// assert!(chain.links_sorted(&[Some(4)]).is_empty());
+    /// ```
+    pub fn links_sorted(&self, node: &[Option<T>]) -> Vec<(&Option<T>, W)>
+        where T: Ord {
+        let mut links = match self.links(node) {
+            Some(links) => links.iter()
+                .map(|(next, &weight)| (next, weight))
+                .collect::<Vec<_>>(),
+            None => return vec![],
+        };
+        links.sort_by(|&(a, a_weight), &(b, b_weight)| {
+            b_weight.partial_cmp(&a_weight)
+                .unwrap_or(cmp::Ordering::Equal)
+                .then_with(|| a.cmp(b))
+        });
+        links
+    }
+
+    /// Gets the weight of the link from `node` to `next`, or 0 if there is no
+    /// such link.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2]);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 2);
+    /// assert_eq!(chain.transition_weight(&[Some(2)], &Some(2)), 0);
+    /// ```
+    pub fn transition_weight(&self, node: &[Option<T>], next: &Option<T>) -> W {
+        self.get_weight(node, next)
+            .unwrap_or_else(W::zero)
+    }
+
+    /// Gets the weight of the link from `node` to `next` like
+    /// `transition_weight`, or `None` if there is no such link.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2]);
+    /// assert_eq!(chain.get_weight(&[Some(1)], &Some(2)), Some(2));
+    /// assert_eq!(chain.get_weight(&[Some(2)], &Some(2)), None);
+    /// ```
+    pub fn get_weight(&self, node: &[Option<T>], next: &Option<T>) -> Option<W> {
+        self.links(node)
+            .and_then(|link| link.get(next))
+            .cloned()
+    }
+
+    /// Gets whether the chain has a node, without needing a `Vec` of it.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert!(chain.contains_node(&[Some(1), Some(2)]));
+    /// assert!(!chain.contains_node(&[Some(2), Some(1)]));
+    /// ```
+    pub fn contains_node(&self, node: &[Option<T>]) -> bool {
+        self.chain.contains_key(node)
+    }
+
+    /// Gets the total weight of the links out of a node, or 0 if the node
+    /// isn't in the chain.
+    pub fn total_weight(&self, node: &[Option<T>]) -> W {
+        self.links(node)
+            .map(total_weight)
+            .unwrap_or_else(W::zero)
+    }
+
+    /// Gets how often the chain saw `sequence`, to check that a phrase made it
+    /// into the training. Each run of `order` items in the sequence and the
+    /// item after it is a transition, and this is the smallest weight of those
+    /// transitions: the phrase can't have been trained more often than its
+    /// rarest step. This is 0 if any step was never trained, or if the
+    /// sequence is too short to have a transition at all.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![1, 2, 4]);
+    /// assert_eq!(chain.observed_count(&[1, 2]), 2);
+    /// assert_eq!(chain.observed_count(&[1, 2, 3]), 1);
+    /// assert_eq!(chain.observed_count(&[2, 1]), 0);
+    /// ```
+    pub fn observed_count(&self, sequence: &[T]) -> W {
+        sequence.windows(self.order + 1)
+            .map(|window| {
+                let node = window[.. self.order].iter()
+                    .cloned()
+                    .map(Some)
+                    .collect::<Node<T>>();
+                self.transition_weight(&node, &Some(window[self.order].clone()))
+            })
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal))
+            .unwrap_or_else(W::zero)
+    }
+
+    /// Gets the probability of generating `next` from `node`, which is the
+    /// weight of the link divided by the total weight of the node. This is
+    /// 0.0 when the node or the link doesn't exist.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2, 1, 3]);
+    /// assert_eq!(chain.probability(&[Some(1)], &Some(2)), 2.0 / 3.0);
+    /// assert_eq!(chain.probability(&[Some(1)], &Some(1)), 0.0);
+    /// ```
+    pub fn probability(&self, node: &[Option<T>], next: &Option<T>) -> f64 {
+        let total = self.total_weight(node).to_f64();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.transition_weight(node, next).to_f64() / total
+    }
+
+    /// Gets the probability of generating `next` from `node` like
+    /// `probability`, with add-k (Laplace) smoothing. This is
+    /// `(weight + k) / (total + k * vocab_size)`, so transitions that were
+    /// never trained still get a small probability, as long as `k` is
+    /// positive.
+    ///
+    /// `vocab_size` is the number of distinct items that could follow a node,
+    /// which is usually `vocabulary_size`, plus one if the end of a sequence
+    /// is counted as well.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3]);
+    /// let vocab_size = chain.vocabulary_size();
+    /// assert!(chain.probability_smoothed(&[Some(2)], &Some(3), 1.0, vocab_size) > 0.0);
+    /// ```
+    pub fn probability_smoothed(&self, node: &[Option<T>], next: &Option<T>, k: f64, vocab_size: usize) -> f64 {
+        let total = self.total_weight(node).to_f64() + k * vocab_size as f64;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (self.transition_weight(node, next).to_f64() + k) / total
+    }
+
+    /// Gets the probability of generating `next` from `node` by linear
+    /// interpolation with a lower order chain, as
+    /// `lambda * p_high + (1 - lambda) * p_low`. `p_high` is the
+    /// `probability` in this chain, and `p_low` is the `probability` in
+    /// `lower` from the last `lower.order()` items of `node`. This lets a
+    /// high order chain fall back on a low order chain for contexts it never
+    /// saw, and the result is always between 0.0 and 1.0.
+    /// # Panics
+    /// Panics if the order of `lower` is greater than the order of this
+    /// chain, or if `lambda` is not between 0.0 and 1.0.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut high = Chain::new(2);
+    /// let mut low = Chain::new(1);
+    /// for chain in &mut [&mut high, &mut low] {
+    ///     chain.train(vec![1, 2, 3]);
+    ///     chain.train(vec![4, 2, 5]);
+    /// }
+    /// assert_eq!(high.probability(&[Some(4), Some(2)], &Some(3)), 0.0);
+    /// assert_eq!(high.interpolated_probability(&low, &[Some(4), Some(2)], &Some(3), 0.5), 0.25);
+    /// ```
+    pub fn interpolated_probability(&self, lower: &Self, node: &[Option<T>], next: &Option<T>, lambda: f64) -> f64 {
+        assert!(lower.order <= self.order,
+                "the lower chain must not have a greater order than the higher chain");
+        assert!((0.0 ..= 1.0).contains(&lambda), "lambda must be between 0.0 and 1.0");
+        let lower_node = &node[node.len().saturating_sub(lower.order) ..];
+        let p_high = self.probability(node, next);
+        let p_low = lower.probability(lower_node, next);
+        (lambda * p_high + (1.0 - lambda) * p_low).clamp(0.0, 1.0)
+    }
+
+    /// Scores how well `sequence` fits the chain, as the natural log of the
+    /// probability of generating it. This includes choosing the first item
+    /// from the start of a sequence and ending after the last item, so scores
+    /// of sequences of different lengths can be compared with `perplexity`.
+    ///
+    /// Transitions are scored with `probability_smoothed` using add-one
+    /// smoothing over the vocabulary of the chain and the end of a sequence,
+    /// so transitions that were never trained lower the score rather than
+    /// making it negative infinity.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert!(chain.log_probability(&[1, 2, 3]) > chain.log_probability(&[3, 2, 1]));
+    /// ```
+    pub fn log_probability(&self, sequence: &[T]) -> f64 {
+        let vocab_size = self.vocabulary_size() + 1;
+        let mut curs = vec![None; self.order];
+        let mut score = 0.0;
+        for next in sequence.iter().cloned().map(Some).chain(Some(None)) {
+            score += self.probability_smoothed(&curs, &next, 1.0, vocab_size).ln();
+            curs = advance(&curs, &next);
+        }
+        score
+    }
+
+    /// Gets the perplexity of the chain on `sequence`, which is
+    /// `exp(-log_probability / n)` for the `n` transitions scored by
+    /// `log_probability`, including the end of the sequence. Lower is a
+    /// better fit, and 1.0 is the lowest possible.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert!(chain.perplexity(&[1, 2, 3]) < chain.perplexity(&[3, 2, 1]));
+    /// ```
+    pub fn perplexity(&self, sequence: &[T]) -> f64 {
+        let transitions = (sequence.len() + 1) as f64;
+        (-self.log_probability(sequence) / transitions).exp()
+    }
+
+    /// Gets the Shannon entropy of the links out of `node`, in bits. This is
+    /// how uncertain the choice of the next item is: 0.0 when only one item
+    /// can follow (or the node doesn't exist), and 1.0 for a choice between
+    /// two equally likely items. The end of a sequence counts as an item.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![1, 2, 4]);
+    /// assert_eq!(chain.transition_entropy(&[Some(1)]), 0.0);
+    /// assert_eq!(chain.transition_entropy(&[Some(2)]), 1.0);
+    /// ```
+    pub fn transition_entropy(&self, node: &[Option<T>]) -> f64 {
+        let links = match self.links(node) {
+            Some(links) => links,
+            None => return 0.0,
+        };
+        let total = total_weight(links).to_f64();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        links.values()
+            .map(|weight| weight.to_f64() / total)
+            .filter(|&p| p > 0.0)
+            .fold(0.0, |entropy, p| entropy - p * p.log2())
+    }
+
+    /// Gets the average `transition_entropy` of every node in the chain,
+    /// weighted by how many times each node was trained. This is how random
+    /// the chain is overall, in bits per item: 0.0 means the chain only ever
+    /// generates the same strings.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert_eq!(chain.average_entropy(), 0.0);
+    /// ```
+    pub fn average_entropy(&self) -> f64 {
+        let (total, weighted) = self.chain.iter()
+            .map(|(node, links)| (total_weight(links).to_f64(), self.transition_entropy(node)))
+            .fold((0.0, 0.0), |(total, weighted), (weight, entropy)| (total + weight, weighted + weight * entropy));
+        if total <= 0.0 {
+            return 0.0;
+        }
+        weighted / total
+    }
+
+    /// Gets the number of distinct items that the chain can generate.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3]);
+    /// assert_eq!(chain.vocabulary_size(), 3);
+    /// ```
+    pub fn vocabulary_size(&self) -> usize {
+        self.vocabulary().len()
+    }
+
+    /// Gets every distinct item that the chain can generate, which are the
+    /// items that some link leads to.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3]);
+    /// let vocabulary = chain.vocabulary();
+    /// assert!(vocabulary.contains(&3));
+    /// assert!(!vocabulary.contains(&4));
+    /// ```
+    pub fn vocabulary(&self) -> HashSet<&T> {
+        self.chain.values()
+            .flat_map(|link| link.keys())
+            .flatten()
+            .collect()
+    }
+
+    /// Gets the item most likely to follow `node`, which is the item with the
+    /// heaviest link. The link that ends a sequence is not an item, so it is
+    /// never chosen; this is `None` only when the node doesn't exist or can
+    /// only end a sequence. Ties go to the smallest item, so the result never
+    /// depends on the order links happen to be stored in.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 3, 1, 2, 1, 3]);
+    /// assert_eq!(chain.most_likely_next(&[Some(1)]), Some(&3));
+    /// assert_eq!(chain.most_likely_next(&[Some(3)]), Some(&1));
+    /// assert_eq!(chain.most_likely_next(&[Some(2)]), Some(&1));
+    /// ```
+    pub fn most_likely_next(&self, node: &[Option<T>]) -> Option<&T>
+        where T: Ord {
+        self.links(node)?
+            .iter()
+            .filter_map(|(next, &weight)| next.as_ref().map(|next| (next, weight)))
+            .fold(None, |best: Option<(&T, W)>, (next, weight)| match best {
+                Some((best_next, best_weight))
+                    if best_weight > weight || (best_weight == weight && best_next < next) =>
+                    Some((best_next, best_weight)),
+                _ => Some((next, weight)),
+            })
+            .map(|(next, _)| next)
+    }
+
+    /// Gets the `n` items that trained sequences most often start with, along
+    /// with how often they do, from most to least common. These are the links
+    /// from the node of all `None` padding, where every sequence starts. Ties
+    /// go to the smallest item, so the result never depends on the order links
+    /// happen to be stored in.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2])
+    ///     .train(vec![3, 2])
+    ///     .train(vec![3, 1]);
+    /// assert_eq!(chain.most_common_starts(1), vec![(3, 2)]);
+    /// assert_eq!(chain.most_common_starts(5), vec![(3, 2), (1, 1)]);
+    /// ```
+    pub fn most_common_starts(&self, n: usize) -> Vec<(T, W)>
+        where T: Ord {
+        let mut starts = match self.links(&vec![None; self.order]) {
+            Some(links) => links.iter()
+                .filter_map(|(next, &weight)| next.as_ref().map(|next| (next, weight)))
+                .collect::<Vec<_>>(),
+            None => return vec![],
+        };
+        starts.sort_by(|&(a, a_weight), &(b, b_weight)| {
+            b_weight.partial_cmp(&a_weight)
+                .unwrap_or(cmp::Ordering::Equal)
+                .then_with(|| a.cmp(b))
+        });
+        starts.into_iter()
+            .take(n)
+            .map(|(next, weight)| (next.clone(), weight))
+            .collect()
+    }
+
+    /// Trains a sentence on a string of items.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// let data = vec![10, 15, 20];
+    /// chain.train(data)
+    ///     .train(vec![]);
+    /// ```
+    pub fn train(&mut self, string: Vec<T>) -> &mut Self {
+        self.train_iter(string)
+    }
+
+    /// Trains a sentence on a slice of items, like `train`, without needing a
+    /// `Vec` of them.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// let data = [10, 15, 20];
+    /// chain.train_slice(&data[1 ..]);
+    /// assert_eq!(chain.transition_weight(&[Some(15)], &Some(20)), 1);
+    /// ```
+    pub fn train_slice(&mut self, string: &[T]) -> &mut Self {
+        self.train_iter(string.iter().cloned())
+    }
+
+    /// Trains a sentence on the items of an iterator, like `train`, without
+    /// collecting them first.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// chain.train_iter((1 .. 100).filter(|x| x % 3 == 0));
+    /// assert_eq!(chain.transition_weight(&[Some(3), Some(6)], &Some(9)), 1);
+    /// ```
+    pub fn train_iter<I>(&mut self, items: I) -> &mut Self
+        where I: IntoIterator<Item = T> {
+        self.train_filtered(items, || true)
+    }
+
+    /// Trains a sentence on a string of items, keeping each transition with
+    /// probability `rate`. This gives a faster, approximate model for quick
+    /// iteration on huge corpora; the resulting chain has lower weights and is
+    /// noisier than a fully trained one. A `rate` of `1.0` is the same as
+    /// `train`.
+    /// # Examples
+    /// ```
+    /// extern crate rand;
+    /// # extern crate markov_chain;
+    /// use markov_chain::Chain;
+    /// # fn main() {
+    /// let mut chain = Chain::new(1);
+    /// let mut rng = rand::thread_rng();
+    /// chain.train_sampled(vec![1, 2, 3, 4, 5], 0.5, &mut rng);
+    /// # }
+    /// ```
+    pub fn train_sampled<R: Rng>(&mut self, string: Vec<T>, rate: f64, rng: &mut R) -> &mut Self {
+        self.train_filtered(string, || rng.next_f64() < rate)
+    }
+
+    /// Trains a sentence on a string of items, only keeping the transitions
+    /// for which `keep` returns true. The string only counts as trained, for
+    /// the clock and the recency of its opening, if something is kept.
+    fn train_filtered<I, F>(&mut self, string: I, mut keep: F) -> &mut Self
+        where I: IntoIterator<Item = T>,
+              F: FnMut() -> bool {
+        let order = self.order;
+        let mut count = 0;
+        let mut kept = false;
+        for_each_transition(order, string, |node, next| {
+            if keep() {
+                if !kept {
+                    self.clock += 1;
+                    kept = true;
+                }
+                // the node after the first `order` items opens the string
+                if count == order {
+                    self.recency.insert(node.to_vec(), self.clock);
+                }
+                self.update_link(node, next);
+            }
+            count += 1;
+        });
+        self
+    }
+
+    /// Trains the chain on a string of items and on its reverse, so that
+    /// every transition is learned in both directions.
+    ///
+    /// This is only meaningful when the domain doesn't care about direction,
+    /// and it roughly doubles the size of the chain compared to `train`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_bidirectional(vec![1, 2, 3]);
+    /// ```
+    pub fn train_bidirectional(&mut self, string: Vec<T>) -> &mut Self {
+        let mut reversed = string.clone();
+        reversed.reverse();
+        self.train(string)
+            .train(reversed)
+    }
+
+    /// Trains the chain on the reverse of a string of items, so that it
+    /// learns which items come before each node rather than after it. Chains
+    /// trained this way are generated from with `generate_reverse`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_reverse(vec![1, 2, 3]);
+    /// assert_eq!(chain.transition_weight(&[Some(3)], &Some(2)), 1);
+    /// ```
+    pub fn train_reverse(&mut self, mut string: Vec<T>) -> &mut Self {
+        string.reverse();
+        self.train(string)
+    }
+
+    /// Merges this markov chain with another.
+    ///
+    /// # Panics
+    /// Panics if the orders of the chains differ. See `try_merge`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain1 = Chain::new(1);
+    /// let mut chain2 = chain1.clone();
+    /// chain1.train(vec![1, 2, 3]);
+    /// chain2.train(vec![2, 3, 4, 5, 6])
+    ///     .merge(&chain1);
     /// ```
     pub fn merge(&mut self, other: &Self) -> &mut Self {
-        assert_eq!(self.order, other.order, "orders must be equal in order to merge markov chains");
+        self.try_merge(other)
+            .expect("orders must be equal in order to merge markov chains")
+    }
+
+    /// Merges this markov chain with another like `merge`, giving
+    /// `MergeError::OrderMismatch` instead of panicking if the orders of the
+    /// chains differ.
+    /// # Examples
+    /// ```
+    /// use markov_chain::{Chain, MergeError};
+    /// let mut chain1 = Chain::new(1);
+    /// let mut chain2 = Chain::new(2);
+    /// chain1.train(vec![1, 2, 3]);
+    /// chain2.train(vec![1, 2, 3]);
+    /// assert_eq!(chain1.try_merge(&chain2).unwrap_err(),
+    ///            MergeError::OrderMismatch { self_order: 1, other_order: 2 });
+    /// ```
+    pub fn try_merge(&mut self, other: &Self) -> Result<&mut Self, MergeError> {
+        if self.order != other.order {
+            return Err(MergeError::OrderMismatch { self_order: self.order, other_order: other.order });
+        }
+        self.merge_recency(other);
         if self.chain.is_empty() {
             self.chain = other.chain.clone();
-            return self;
+            self.rebuild_caches();
+            return Ok(self);
         }
 
         for (node, link) in &other.chain {
-            for (ref next, &weight) in link.iter() {
+            for (next, &weight) in link.iter() {
                 self.update_link_weight(node, next, weight);
             }
         }
-        self
+        Ok(self)
     }
 
-    /// Increments a link from a node by one, or adding it with a weight of 1
-    /// if it doesn't exist.
-    fn update_link(&mut self, node: &[Option<T>], next: &Option<T>) {
-        self.update_link_weight(node, next, 1);
+    /// Merges all of the given markov chains into a new one, as if each were
+    /// merged in turn into a copy of the first. The settings of the new chain,
+    /// such as its break tokens, come from the first chain. This is `None` if
+    /// there are no chains.
+    ///
+    /// # Panics
+    /// Panics if the orders of the chains differ. See `try_merge_all`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chains = vec![Chain::new(1), Chain::new(1)];
+    /// chains[0].train(vec![1, 2, 3]);
+    /// chains[1].train(vec![2, 3, 4]);
+    /// let merged = Chain::merge_all(&chains).unwrap();
+    /// assert_eq!(merged.transition_weight(&[Some(2)], &Some(3)), 2);
+    /// assert!(Chain::<u32>::merge_all(&[]).is_none());
+    /// ```
+    pub fn merge_all(chains: &[Self]) -> Option<Self> {
+        match Self::try_merge_all(chains) {
+            Ok(chain) => Some(chain),
+            Err(MergeError::Empty) => None,
+            Err(_) => panic!("orders must be equal in order to merge markov chains"),
+        }
     }
 
-    /// Increments a link from a node by specified value, or adding it with a
-    /// weight of the specified value if it doesn't exist.
-    fn update_link_weight(&mut self, node: &[Option<T>], next: &Option<T>, weight: u32) {
-        if self.chain.contains_key(node) {
-            let links = self.chain
-                .get_mut(node)
-                .unwrap();
-            // Update the link
-            if links.contains_key(next) {
-                let weight = *links.get(next).unwrap() + weight;
-                links.insert(next.clone(), weight);
-            }
-            // Insert a new link
-            else {
-                links.insert(next.clone(), weight);
+    /// Merges all of the given markov chains into a new one like `merge_all`,
+    /// giving `MergeError::Empty` if there are no chains and
+    /// `MergeError::OrderMismatch` instead of panicking if their orders
+    /// differ.
+    /// # Examples
+    /// ```
+    /// use markov_chain::{Chain, MergeError};
+    /// let chains = vec![Chain::<u32>::new(1), Chain::new(1), Chain::new(2)];
+    /// assert_eq!(Chain::try_merge_all(&chains).unwrap_err(),
+    ///            MergeError::OrderMismatch { self_order: 1, other_order: 2 });
+    /// assert_eq!(Chain::<u32>::try_merge_all(&[]).unwrap_err(), MergeError::Empty);
+    /// ```
+    pub fn try_merge_all(chains: &[Self]) -> Result<Self, MergeError> {
+        let first = match chains.first() {
+            Some(first) => first,
+            None => return Err(MergeError::Empty),
+        };
+        if let Some(other) = chains.iter().find(|chain| chain.order != first.order) {
+            return Err(MergeError::OrderMismatch { self_order: first.order, other_order: other.order });
+        }
+        let mut result = first.clone();
+        for chain in &chains[1 ..] {
+            result.try_merge(chain)?;
+        }
+        Ok(result)
+    }
+
+    /// Merges this markov chain with another like `merge`, scaling the weight
+    /// of every link from `other` by `factor` before adding it. This tunes how
+    /// much influence `other` has, such as giving a small domain-specific
+    /// chain more say than a general one.
+    ///
+    /// For integer weights, the scaled weights are rounded to the nearest
+    /// whole weight, but never below 1, so no link from `other` is lost. A
+    /// factor of zero or less adds nothing.
+    ///
+    /// # Panics
+    /// Panics if the orders of the chains differ.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut general = Chain::new(1);
+    /// let mut domain = Chain::new(1);
+    /// general.train(vec![1, 2, 3]);
+    /// domain.train(vec![1, 2, 4]);
+    /// general.merge_weighted(&domain, 3.0);
+    /// assert_eq!(general.transition_weight(&[Some(2)], &Some(3)), 1);
+    /// assert_eq!(general.transition_weight(&[Some(2)], &Some(4)), 3);
+    /// ```
+    pub fn merge_weighted(&mut self, other: &Self, factor: f64) -> &mut Self {
+        assert_eq!(self.order, other.order, "orders must be equal in order to merge markov chains");
+        if factor <= 0.0 {
+            return self;
+        }
+        self.merge_recency(other);
+        for (node, link) in &other.chain {
+            for (next, &weight) in link {
+                if weight <= W::zero() {
+                    continue;
+                }
+                let mut scaled = W::from_f64(weight.to_f64() * factor);
+                if scaled <= W::zero() {
+                    scaled = W::one();
+                }
+                self.update_link_weight(node, next, scaled);
             }
         }
-        else {
-            self.chain.insert(Vec::from(node), hashmap!{next.clone() => weight});
+        self
+    }
+
+    /// Merges this markov chain with another, keeping the larger of the two
+    /// weights for every link instead of adding them together.
+    ///
+    /// Summing with `merge` is right when the two chains were trained on
+    /// different data. When both chains are views of the same underlying data,
+    /// summing would count shared observations twice, and taking the maximum
+    /// avoids that.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain1 = Chain::new(1);
+    /// let mut chain2 = chain1.clone();
+    /// chain1.train(vec![1, 2, 3]);
+    /// chain2.train(vec![1, 2, 3])
+    ///     .merge_max(&chain1);
+    /// assert_eq!(chain1, chain2);
+    /// ```
+    pub fn merge_max(&mut self, other: &Self) -> &mut Self {
+        assert_eq!(self.order, other.order, "orders must be equal in order to merge markov chains");
+        self.merge_recency(other);
+        for (node, link) in &other.chain {
+            let links = self.chain
+                .entry(node.clone())
+                .or_default();
+            for (next, &weight) in link {
+                let current = links.entry(next.clone())
+                    .or_insert(weight);
+                if weight > *current {
+                    *current = weight;
+                }
+            }
         }
+        self.rebuild_caches();
+        self
+    }
+
+    /// Combines several chains into a new one, scaling the link weights of
+    /// each chain by the factor paired with it.
+    ///
+    /// Scaled weights are summed for each link and then, for integer weights,
+    /// rounded to the nearest whole weight, so links whose combined weight
+    /// rounds to zero are left out. A chain with a factor of zero or less contributes nothing.
+    /// An empty list of chains gives `MergeError::Empty`, since there is no
+    /// order to give the new chain, and chains of differing orders give
+    /// `MergeError::OrderMismatch`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain1 = Chain::new(1);
+    /// let mut chain2 = chain1.clone();
+    /// chain1.train(vec![1, 2, 3]);
+    /// chain2.train(vec![1, 2, 4]);
+    /// let blended = Chain::blend(&[(&chain1, 3.0), (&chain2, 1.0)]).unwrap();
+    /// assert_eq!(blended.order(), 1);
+    /// ```
+    pub fn blend(chains: &[(&Self, f64)]) -> Result<Self, MergeError> {
+        let order = match chains.first() {
+            Some(&(first, _)) => first.order,
+            None => return Err(MergeError::Empty),
+        };
+        if let Some(&(other, _)) = chains.iter().find(|&&(chain, _)| chain.order != order) {
+            return Err(MergeError::OrderMismatch { self_order: order, other_order: other.order });
+        }
+
+        let mut scaled: HashMap<&Node<T>, HashMap<&Option<T>, f64>> = HashMap::new();
+        let mut result = chains[0].0.empty_like(order);
+        for &(chain, factor) in chains {
+            if factor <= 0.0 {
+                continue;
+            }
+            result.merge_recency(chain);
+            for (node, link) in &chain.chain {
+                let links = scaled.entry(node).or_default();
+                for (next, &weight) in link {
+                    *links.entry(next).or_insert(0.0) += weight.to_f64() * factor;
+                }
+            }
+        }
+        for (node, link) in scaled {
+            for (next, weight) in link {
+                let weight = W::from_f64(weight);
+                if weight > W::zero() {
+                    result.update_link_weight(node, next, weight);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Gets the changes in link weights that turn `base` into this chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut base = Chain::new(1);
+    /// base.train(vec![1, 2, 3]);
+    /// let mut updated = base.clone();
+    /// updated.train(vec![1, 2, 4]);
+    /// let delta = updated.diff(&base);
+    /// base.apply_delta(&delta);
+    /// assert_eq!(base, updated);
+    /// ```
+    pub fn diff(&self, base: &Self) -> ChainDelta<T, W> {
+        assert_eq!(self.order, base.order, "orders must be equal in order to diff markov chains");
+        let mut added: HashMap<Node<T>, Link<T, W>> = HashMap::new();
+        let mut removed: HashMap<Node<T>, Link<T, W>> = HashMap::new();
+        let empty = HashMap::default();
+        // links that were added or changed
+        for (node, link) in &self.chain {
+            let base_link = base.chain.get(node).unwrap_or(&empty);
+            for (next, &weight) in link {
+                let base_weight = base_link.get(next).cloned().unwrap_or_else(W::zero);
+                if weight > base_weight {
+                    added.entry(node.clone())
+                        .or_default()
+                        .insert(next.clone(), weight.saturating_sub(base_weight));
+                }
+                else if weight < base_weight {
+                    removed.entry(node.clone())
+                        .or_default()
+                        .insert(next.clone(), base_weight.saturating_sub(weight));
+                }
+            }
+        }
+        // links that were removed
+        for (node, base_link) in &base.chain {
+            let link = self.chain.get(node).unwrap_or(&empty);
+            for (next, &base_weight) in base_link {
+                if !link.contains_key(next) {
+                    removed.entry(node.clone())
+                        .or_default()
+                        .insert(next.clone(), base_weight);
+                }
+            }
+        }
+        ChainDelta {
+            added,
+            removed,
+            order: self.order,
+        }
+    }
+
+    /// Applies the changes in link weights from a delta made by `diff`. Links
+    /// whose weight drops to zero are removed, along with any nodes that are
+    /// left without links.
+    pub fn apply_delta(&mut self, delta: &ChainDelta<T, W>) -> &mut Self {
+        assert_eq!(self.order, delta.order, "orders must be equal in order to apply a delta");
+        for (node, changes) in &delta.added {
+            let links = self.chain
+                .entry(node.clone())
+                .or_default();
+            for (next, &change) in changes {
+                let weight = links.entry(next.clone())
+                    .or_insert_with(W::zero);
+                *weight = weight.saturating_add(change);
+            }
+        }
+        for (node, changes) in &delta.removed {
+            if let Some(links) = self.chain.get_mut(node) {
+                for (next, &change) in changes {
+                    let weight = links.get(next).cloned().unwrap_or_else(W::zero).saturating_sub(change);
+                    if weight > W::zero() {
+                        links.insert(next.clone(), weight);
+                    }
+                    else {
+                        links.remove(next);
+                    }
+                }
+            }
+        }
+        self.remove_empty_nodes();
+        self
+    }
+
+    /// Merges the training recency of another chain into this one. Since the
+    /// two chains were trained separately, their clocks are only roughly
+    /// comparable, and the more recent of the two is kept for each node.
+    fn merge_recency(&mut self, other: &Self) {
+        for (node, &time) in &other.recency {
+            let current = self.recency.entry(node.clone())
+                .or_insert(0);
+            *current = cmp::max(*current, time);
+        }
+        self.clock = cmp::max(self.clock, other.clock);
+    }
+
+    /// Removes every node that generation can never get to, to reclaim memory.
+    /// Generation starts from a starting node (see `starting_nodes`) or, when
+    /// it starts at random, from any complete node, so those are kept along
+    /// with every node they lead to. Since only nodes that nothing can ever
+    /// generate from are removed, compacting never changes what is generated.
+    ///
+    /// When the chain has no complete nodes, a random start can be any node at
+    /// all, so nothing is removed.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .compact();
+    /// ```
+    pub fn compact(&mut self) -> &mut Self {
+        if self.starts.is_empty() {
+            return self;
+        }
+        let mut reachable = self.starting_nodes()
+            .chain(&self.starts)
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut queue = reachable.iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        while let Some(node) = queue.pop() {
+            for next in self.chain[&node].keys().filter(|next| next.is_some()) {
+                let target = advance(&node, next);
+                if self.chain.contains_key(&target) && !reachable.contains(&target) {
+                    reachable.insert(target.clone());
+                    queue.push(target);
+                }
+            }
+        }
+        self.chain.retain(|node, _| reachable.contains(node));
+        self.rebuild_caches();
+        self
+    }
+
+    /// Removes every link whose weight is below `min_weight`, which are mostly
+    /// rare transitions that bloat the chain without improving generation
+    /// much. Nodes that are left without links are removed too, including
+    /// starting nodes, so no empty nodes are left behind.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2, 1, 3])
+    ///     .prune(2);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 2);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(3)), 0);
+    /// ```
+    pub fn prune(&mut self, min_weight: W) -> &mut Self {
+        for link in self.chain.values_mut() {
+            link.retain(|_, &mut weight| weight >= min_weight);
+        }
+        self.remove_empty_nodes();
+        self
+    }
+
+    /// Removes every item that isn't in `allowed` from the chain: links to
+    /// other items, and nodes that contain them, are removed, along with any
+    /// nodes left without links. Links that end a sequence are kept. The chain
+    /// can't generate a removed item afterwards, which makes this useful for
+    /// filtering out a blocklist of words from a trained chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// use std::collections::HashSet;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![1, 4, 3]);
+    /// let allowed = vec![1, 3, 4].into_iter().collect::<HashSet<_>>();
+    /// chain.restrict_vocabulary(&allowed);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 0);
+    /// assert!(!chain.contains_node(&[Some(2)]));
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(4)), 1);
+    /// ```
+    pub fn restrict_vocabulary(&mut self, allowed: &HashSet<T>) -> &mut Self {
+        self.chain.retain(|node, _| node.iter().flatten().all(|item| allowed.contains(item)));
+        for link in self.chain.values_mut() {
+            link.retain(|next, _| next.iter().all(|item| allowed.contains(item)));
+        }
+        self.remove_empty_nodes();
+        self
+    }
+
+    /// Multiplies every link weight by `factor`, which should be between 0
+    /// and 1, so that older training counts for less than training done
+    /// afterwards. Integer weights are rounded, and links whose weight drops
+    /// to zero are removed, along with any nodes left without links.
+    ///
+    /// Decaying is meant to be done periodically between rounds of training;
+    /// with a factor of 0.9, the influence of earlier training roughly halves
+    /// every 7 rounds.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2, 1, 3])
+    ///     .decay(0.5);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 1);
+    /// // 0.5 rounds away from zero, so single observations survive one decay
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(3)), 1);
+    /// ```
+    pub fn decay(&mut self, factor: f64) -> &mut Self {
+        for link in self.chain.values_mut() {
+            for weight in link.values_mut() {
+                *weight = W::from_f64(weight.to_f64() * factor);
+            }
+            link.retain(|_, &mut weight| weight > W::zero());
+        }
+        self.remove_empty_nodes();
+        self
+    }
+
+    /// Builds a new chain of a lower order from the transitions of this one,
+    /// for trying out a different order without the original training data.
+    /// This is `None` if `new_order` is 0 or higher than the order of this
+    /// chain, since a higher order needs longer runs of items than the chain
+    /// remembers.
+    ///
+    /// Each link is kept with its weight, but from the node made of the last
+    /// `new_order` items of its old node. This gives the same chain as
+    /// training the original strings at the new order would, except for
+    /// strings shorter than the new order. Those are padded out with `None` in
+    /// training, and the links made by that padding can't be told apart from
+    /// the padding of the old order, so they are lost.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(3);
+    /// chain.train(vec![1, 2, 3, 4, 2, 3, 5]);
+    /// let lower = chain.retrain_order(1).unwrap();
+    /// let mut expected = Chain::new(1);
+    /// expected.train(vec![1, 2, 3, 4, 2, 3, 5]);
+    /// assert_eq!(lower, expected);
+    /// assert!(chain.retrain_order(4).is_none());
+    /// ```
+    pub fn retrain_order(&self, new_order: usize) -> Option<Self> {
+        if new_order == 0 || new_order > self.order {
+            return None;
+        }
+        let mut chain = self.empty_like(new_order);
+        for (node, link) in &self.chain {
+            // a `None` after an item is padding at the end of a short string
+            let padded = node.iter()
+                .skip_while(|item| item.is_none())
+                .any(Option::is_none);
+            if padded {
+                continue;
+            }
+            let suffix = &node[self.order - new_order ..];
+            for (next, &weight) in link {
+                chain.update_link_weight(suffix, next, weight);
+            }
+        }
+        for (node, &time) in &self.recency {
+            let opening = node[.. new_order].to_vec();
+            let current = chain.recency.entry(opening)
+                .or_insert(time);
+            *current = cmp::max(*current, time);
+        }
+        chain.clock = self.clock;
+        chain.rebuild_caches();
+        Some(chain)
+    }
+
+    /// Builds a new chain of another order, with the same settings as this
+    /// one, trained on `strings`. A chain doesn't keep what it was trained on,
+    /// so `strings` must be the original training data, supplied again; none
+    /// of the transitions of this chain are used. To lower the order without
+    /// the original data, at the cost of a few transitions, see
+    /// `retrain_order`.
+    ///
+    /// This gives an error instead of panicking if `order` is 0.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let strings = vec![vec![1, 2, 3], vec![2, 3, 4]];
+    /// let mut chain = Chain::new(1);
+    /// chain.extend(strings.clone());
+    /// let rebuilt = chain.rebuild_at_order(2, strings.clone()).unwrap();
+    /// assert_eq!(rebuilt.order(), 2);
+    /// assert!(rebuilt.contains_node(&[Some(2), Some(3)]));
+    /// assert!(chain.rebuild_at_order(0, strings).is_err());
+    /// ```
+    pub fn rebuild_at_order<I>(&self, order: usize, strings: I) -> Result<Self, ChainError>
+        where I: IntoIterator<Item = Vec<T>> {
+        if order == 0 {
+            return Err(ChainError::ZeroOrder);
+        }
+        let mut chain = self.empty_like(order);
+        for string in strings {
+            chain.train(string);
+        }
+        Ok(chain)
+    }
+
+    /// Removes everything the chain has been trained on, keeping its order and
+    /// settings.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// chain.train(vec![1, 2, 3])
+    ///     .clear();
+    /// assert!(chain.is_empty());
+    /// assert_eq!(chain.order(), 2);
+    /// ```
+    pub fn clear(&mut self) -> &mut Self {
+        *self = self.empty_like(self.order);
+        self
+    }
+
+    /// Unlearns a string of items, taking away each transition that training
+    /// it would have added. Links whose weight drops to zero are removed, and
+    /// so are nodes left without links, so training a string and then
+    /// forgetting it leaves the chain as it was. Transitions the chain doesn't
+    /// have are ignored.
+    ///
+    /// The training recency of the string is rolled back too. Its opening is
+    /// forgotten once no other trained string opens the same way, and if it
+    /// was the last string trained, the clock is turned back by one.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![1, 2, 4])
+    ///     .forget(&[1, 2, 4]);
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 1);
+    /// assert_eq!(chain.transition_weight(&[Some(2)], &Some(4)), 0);
+    /// ```
+    pub fn forget(&mut self, sequence: &[T]) -> &mut Self {
+        let order = self.order;
+        let mut count = 0;
+        // the node the sequence opens with, and the weight left on the
+        // transition into it, which is how many trained sequences still open
+        // the same way
+        let mut opening = None;
+        let mut opening_weight = None;
+        {
+            let chain = &mut self.chain;
+            for_each_transition(order, sequence.iter().cloned(), |node, next| {
+                let mut left = None;
+                if let Some(links) = chain.get_mut(node) {
+                    let weight = links.get(next).map(|weight| weight.saturating_sub(W::one()));
+                    match weight {
+                        Some(weight) if weight > W::zero() => {
+                            links.insert(next.clone(), weight);
+                        },
+                        Some(_) => {
+                            links.remove(next);
+                        },
+                        None => {},
+                    }
+                    left = weight;
+                }
+                if count + 1 == order {
+                    opening_weight = left;
+                }
+                if count == order {
+                    opening = Some(node.to_vec());
+                }
+                count += 1;
+            });
+        }
+        if let (Some(opening), Some(weight)) = (opening, opening_weight) {
+            self.forget_recency(&opening, weight > W::zero());
+        }
+        self.remove_empty_nodes();
+        self
+    }
+
+    /// Rolls back the training recency of a forgotten sequence that opened
+    /// with `opening`, which other trained sequences still open with if
+    /// `still_opens` is true.
+    fn forget_recency(&mut self, opening: &[Option<T>], still_opens: bool) {
+        let time = match self.recency.get(opening) {
+            Some(&time) => time,
+            None => return,
+        };
+        if !still_opens {
+            self.recency.remove(opening);
+        }
+        if time == self.clock && self.clock > 0 {
+            // the forgotten sequence was the last one trained, so the time it
+            // was trained at can be taken back; whatever was trained at the
+            // same time, by a merge, is moved back with it
+            self.clock -= 1;
+            let clock = self.clock;
+            for time in self.recency.values_mut() {
+                *time = cmp::min(*time, clock);
+            }
+        }
+    }
+
+    /// Shrinks the capacity of the chain's maps as much as possible, freeing
+    /// the memory left over after training or pruning. The chain itself is not
+    /// changed.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![4, 5, 6])
+    ///     .forget(&[4, 5, 6])
+    ///     .shrink_to_fit();
+    /// assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 1);
+    /// assert!(chain.links(&[Some(4)]).is_none());
+    /// ```
+    pub fn shrink_to_fit(&mut self) -> &mut Self {
+        for links in self.chain.values_mut() {
+            links.shrink_to_fit();
+        }
+        self.chain.shrink_to_fit();
+        self.recency.shrink_to_fit();
+        self.starts.shrink_to_fit();
+        self
+    }
+
+    /// Removes every node that has no links, along with its training recency.
+    fn remove_empty_nodes(&mut self) {
+        self.chain.retain(|_, link| !link.is_empty());
+        let chain = &self.chain;
+        self.recency.retain(|node, _| chain.contains_key(node));
+        self.rebuild_caches();
+    }
+
+    /// Removes every node whose total outgoing weight is below
+    /// `min_total_weight`, dropping contexts that were seen too rarely to be
+    /// trusted. Links store items rather than nodes, so nothing is left
+    /// pointing at a removed node; generation that reaches one just ends there.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2, 3])
+    ///     .prune_nodes(2);
+    /// assert_eq!(chain.chain().len(), 2);
+    /// ```
+    pub fn prune_nodes(&mut self, min_total_weight: W) -> &mut Self {
+        self.chain.retain(|_, link| total_weight(link) >= min_total_weight);
+        self.rebuild_caches();
+        self
+    }
+
+    /// Gets whether generation is able to terminate from every node in the
+    /// chain, by eventually reaching either a terminal link or a node with no
+    /// links. When this is false, there's a group of nodes that only link
+    /// among themselves, and generation that enters it loops until it hits its
+    /// limit. Use `non_terminating_nodes` to find those nodes.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 1, 2, 3]);
+    /// assert!(chain.has_absorbing_paths());
+    /// ```
+    pub fn has_absorbing_paths(&self) -> bool {
+        self.non_terminating_nodes().is_empty()
+    }
+
+    /// Gets every node from which generation can never terminate. See
+    /// `has_absorbing_paths`.
+    pub fn non_terminating_nodes(&self) -> Vec<&Node<T>> {
+        // Find the nodes that can terminate directly, and remember which nodes
+        // lead into which so that termination can be propagated backwards.
+        let mut predecessors: HashMap<Node<T>, Vec<&Node<T>>> = HashMap::new();
+        let mut terminating = HashSet::new();
+        let mut queue = Vec::new();
+        for (node, link) in &self.chain {
+            for next in link.keys() {
+                let target = advance(node, next);
+                if next.is_some() && self.chain.contains_key(&target) {
+                    predecessors.entry(target)
+                        .or_default()
+                        .push(node);
+                }
+                else if terminating.insert(node) {
+                    queue.push(node);
+                }
+            }
+        }
+
+        while let Some(node) = queue.pop() {
+            if let Some(preds) = predecessors.get(node) {
+                for &pred in preds {
+                    if terminating.insert(pred) {
+                        queue.push(pred);
+                    }
+                }
+            }
+        }
+        self.chain.keys()
+            .filter(|node| !terminating.contains(node))
+            .collect()
+    }
+
+    /// Gets whether a node is a dead end, which is a node in the chain whose
+    /// only link is to the end of a sequence, so generation always stops
+    /// there. This is false for nodes that aren't in the chain at all, which
+    /// also stop generation, but because nothing was trained from them.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert!(chain.is_dead_end(&[Some(3)]));
+    /// assert!(!chain.is_dead_end(&[Some(2)]));
+    /// assert!(!chain.is_dead_end(&[Some(4)]));
+    /// ```
+    pub fn is_dead_end(&self, node: &[Option<T>]) -> bool {
+        self.links(node)
+            .is_some_and(|link| !link.is_empty() && link.keys().all(Option::is_none))
+    }
+
+    /// Gets every dead end in the chain, in an arbitrary order. See
+    /// `is_dead_end`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![2, 1, 3]);
+    /// assert_eq!(chain.dead_ends(), vec![&vec![Some(3)]]);
+    /// ```
+    pub fn dead_ends(&self) -> Vec<&Node<T>> {
+        self.chain.keys()
+            .filter(|node| self.is_dead_end(node))
+            .collect()
+    }
+
+    /// Gets the `n` lowest-weight transitions across the whole chain, as
+    /// `(node, next, weight)` triples ordered from rarest upwards. These are
+    /// good candidates for pruning, or a sign of noisy training data.
+    ///
+    /// Only `n` transitions are kept in memory while the chain is scanned.
+    /// Transitions with equal weights are ordered by a fixed hash of their
+    /// node and next item, so which of several tied links makes the cut is the
+    /// same from run to run.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 2, 3]);
+    /// let rarest = chain.rarest_links(1);
+    /// assert_eq!(rarest[0].2, 1);
+    /// ```
+    pub fn rarest_links(&self, n: usize) -> Vec<(&Node<T>, &Option<T>, W)> {
+        if n == 0 {
+            return vec![];
+        }
+        // a max-heap of the rarest links so far, so the most common of them is
+        // the one evicted when a rarer link turns up
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (node, link) in &self.chain {
+            for (next, &weight) in link {
+                heap.push(RareLink { weight, tie: stable_hash(&(node, next)), node, next });
+                if heap.len() > n {
+                    heap.pop();
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|rare| (rare.node, rare.next, rare.weight))
+            .collect()
+    }
+
+    /// Gets every node paired with its total outgoing weight, ordered from the
+    /// busiest node downwards. The total weight of a node is the number of
+    /// times it was seen followed by something in training.
+    ///
+    /// Nodes with equal totals are returned in an arbitrary order.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 2, 3, 2]);
+    /// let nodes = chain.nodes_by_activity();
+    /// assert_eq!(nodes[0], (&vec![Some(2)], 3));
+    /// ```
+    pub fn nodes_by_activity(&self) -> Vec<(&Node<T>, W)> {
+        let mut nodes = self.chain.iter()
+            .map(|(node, link)| (node, total_weight(link)))
+            .collect::<Vec<_>>();
+        nodes.sort_by(|&(_, a), &(_, b)| b.partial_cmp(&a).unwrap_or(cmp::Ordering::Equal));
+        nodes
+    }
+
+    /// Computes the expected number of items that generation produces after
+    /// the context `start` before terminating. Only the last `order` items of
+    /// `start` are used; a shorter `start` is treated as the beginning of a
+    /// sequence.
+    ///
+    /// This treats the chain as an absorbing markov chain, and approximates
+    /// the expected time to absorption by iterating over the nodes reachable
+    /// from `start` until the estimate settles. Reaching a node that isn't in
+    /// the chain counts as terminating. If the estimate doesn't settle within
+    /// a fixed number of iterations, which is the case when generation can
+    /// loop forever without reaching a terminal link, this returns infinity.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert!((chain.expected_length(&[1]) - 2.0).abs() < 1e-6);
+    /// ```
+    pub fn expected_length(&self, start: &[T]) -> f64 {
+        const MAX_ITERATIONS: usize = 10_000;
+        const EPSILON: f64 = 1e-9;
+
+        let start = self.cursor_from(start);
+        if !self.chain.contains_key(&start) {
+            return 0.0;
+        }
+
+        // Index every node reachable from the start node, along with its
+        // transitions as (target index, probability) pairs. A target of `None`
+        // means the item ends up at a node that isn't in the chain.
+        let mut index = hashmap!{start.clone() => 0};
+        let mut nodes = vec![start];
+        let mut transitions = Vec::new();
+        let mut i = 0;
+        while i < nodes.len() {
+            let link = &self.chain[&nodes[i]];
+            let total = link.values()
+                .map(|w| w.to_f64())
+                .sum::<f64>();
+            let mut edges = Vec::new();
+            for (next, &weight) in link {
+                if next.is_none() {
+                    continue;
+                }
+                let target = advance(&nodes[i], next);
+                let target = if self.chain.contains_key(&target) {
+                    let len = index.len();
+                    let t = *index.entry(target.clone())
+                        .or_insert(len);
+                    if t == nodes.len() {
+                        nodes.push(target);
+                    }
+                    Some(t)
+                }
+                else {
+                    None
+                };
+                edges.push((target, weight.to_f64() / total));
+            }
+            transitions.push(edges);
+            i += 1;
+        }
+
+        let mut expected = vec![0.0; nodes.len()];
+        for _ in 0 .. MAX_ITERATIONS {
+            let mut delta: f64 = 0.0;
+            for (i, edges) in transitions.iter().enumerate() {
+                let value = edges.iter()
+                    .map(|&(target, p)| p * (1.0 + target.map(|t| expected[t]).unwrap_or(0.0)))
+                    .sum::<f64>();
+                delta = delta.max((value - expected[i]).abs() / value.max(1.0));
+                expected[i] = value;
+            }
+            if delta < EPSILON {
+                return expected[0];
+            }
+        }
+        f64::INFINITY
+    }
+
+    /// Counts the distinct sequences of at most `max_len` items that
+    /// generation can produce when it starts from the beginning of a sequence,
+    /// as `generate_sentence` does. A sequence counts when it ends on a
+    /// terminal link, or on a node that isn't in the chain.
+    ///
+    /// The number of sequences grows exponentially with `max_len` on most
+    /// chains, so the count saturates at `u64::MAX` instead of overflowing.
+    /// Counting itself takes time proportional to `max_len` times the number
+    /// of links, and bounding the length keeps cycles in the chain from being
+    /// followed forever.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2])
+    ///     .train(vec![1, 3]);
+    /// assert_eq!(chain.count_paths(2), 2);
+    /// assert_eq!(chain.count_paths(1), 0);
+    /// ```
+    pub fn count_paths(&self, max_len: usize) -> u64 {
+        // paths[node] is the number of sequences from the node that end
+        // within the remaining length, starting with no length remaining.
+        let mut paths = self.chain.iter()
+            .map(|(node, link)| (node, if link.contains_key(&None) { 1 } else { 0 }))
+            .collect::<HashMap<_, u64>>();
+        for _ in 0 .. max_len {
+            paths = self.chain.iter()
+                .map(|(node, link)| {
+                    let count = link.keys()
+                        .map(|next| {
+                            if next.is_none() {
+                                return 1;
+                            }
+                            let target = advance(node, next);
+                            paths.get(&target).cloned().unwrap_or(1)
+                        })
+                        .fold(0u64, |total, count| total.saturating_add(count));
+                    (node, count)
+                })
+                .collect();
+        }
+        paths.get(&vec![None; self.order]).cloned().unwrap_or(0)
+    }
+
+    /// Renders the chain as a Graphviz `digraph`, which can be drawn with
+    /// `dot`. Each node is labeled with its items separated by spaces, with
+    /// `__START__` standing in for the padding at the start of a sequence, and
+    /// each link is an edge labeled with its weight. Links that end a sequence
+    /// point to a single `__END__` node.
+    ///
+    /// Nodes and edges are sorted by their labels, so the same chain always
+    /// renders the same way.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2]);
+    /// let dot = chain.to_dot();
+    /// assert!(dot.starts_with("digraph {"));
+    /// assert!(dot.contains("\"1\" -> \"2\" [label=\"1\"];"));
+    /// assert!(dot.contains("\"2\" -> \"__END__\" [label=\"1\"];"));
+    /// ```
+    pub fn to_dot(&self) -> String
+        where T: fmt::Display,
+              W: fmt::Display {
+        fn quote(label: &str) -> String {
+            format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let label = |node: &[Option<T>]| node.iter()
+            .map(|item| match *item {
+                Some(ref item) => item.to_string(),
+                None => String::from("__START__"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut nodes = self.chain.keys()
+            .map(|node| quote(&label(node)))
+            .collect::<Vec<_>>();
+        nodes.sort();
+        let mut edges = self.chain.iter()
+            .flat_map(|(node, link)| link.iter().map(move |(next, weight)| (node, next, weight)))
+            .map(|(node, next, weight)| {
+                let target = match *next {
+                    Some(_) => label(&advance(node, next)),
+                    None => String::from("__END__"),
+                };
+                format!("{} -> {} [label=\"{}\"];", quote(&label(node)), quote(&target), weight)
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+
+        let mut dot = String::from("digraph {\n");
+        dot += "    \"__END__\" [shape=doublecircle];\n";
+        for node in nodes {
+            dot += &format!("    {};\n", node);
+        }
+        for edge in edges {
+            dot += &format!("    {}\n", edge);
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Builds a node from the last `order` items of a string, padding the
+    /// front with `None` if the string is shorter than the order.
+    fn cursor_from(&self, string: &[T]) -> Node<T> {
+        let tail = &string[string.len().saturating_sub(self.order) ..];
+        let mut curs = vec![None; self.order - tail.len()];
+        curs.extend(tail.iter().cloned().map(Some));
+        curs
+    }
+
+    /// Increments a link from a node by one, or adding it with a weight of 1
+    /// if it doesn't exist.
+    fn update_link(&mut self, node: &[Option<T>], next: &Option<T>) {
+        self.update_link_weight(node, next, W::one());
+    }
+
+    /// Increments a link from a node by specified value, or adding it with a
+    /// weight of the specified value if it doesn't exist.
+    fn update_link_weight(&mut self, node: &[Option<T>], next: &Option<T>, weight: W) {
+        self.unigram = OnceLock::new();
+        if self.chain.contains_key(node) {
+            let links = self.chain
+                .get_mut(node)
+                .unwrap();
+            // Update the link
+            if links.contains_key(next) {
+                let weight = links.get(next).unwrap().saturating_add(weight);
+                links.insert(next.clone(), weight);
+            }
+            // Insert a new link
+            else {
+                links.insert(next.clone(), weight);
+            }
+        }
+        else {
+            let mut links = HashMap::default();
+            links.insert(next.clone(), weight);
+            self.chain.insert(Vec::from(node), links);
+            if node.iter().all(Option::is_some) {
+                self.starts.push(Vec::from(node));
+            }
+        }
+    }
+
+    /// Rebuilds the list of complete nodes used to choose starting nodes, and
+    /// throws away the item totals used for backing off, after the chain has
+    /// changed.
+    fn rebuild_caches(&mut self) {
+        self.starts = self.chain.keys()
+            .filter(|node| node.iter().all(Option::is_some))
+            .cloned()
+            .collect();
+        self.unigram = OnceLock::new();
+    }
+
+    /// Gets how often each item was trained, building it if the chain has
+    /// changed since it was last needed.
+    fn unigram(&self) -> &Unigram<T> {
+        self.unigram.get_or_init(|| {
+            let mut totals = HashMap::new();
+            for link in self.chain.values() {
+                for (next, &weight) in link {
+                    if let Some(ref item) = *next {
+                        let total = totals.entry(item.clone())
+                            .or_insert_with(W::zero);
+                        *total = total.saturating_add(weight);
+                    }
+                }
+            }
+            Unigram::new(totals)
+        })
+    }
+
+    /// Chooses a random item to follow the dead end `node`, weighted by how
+    /// often it was trained, along with how likely it was to be chosen out of
+    /// every item. Only items that lead to a node in the chain and are
+    /// accepted by `allowed`, if given, are chosen.
+    fn choose_unigram<S>(&self, node: &[Option<T>], allowed: Option<&dyn Fn(&T) -> bool>, rng: &mut S)
+        -> Option<(&T, f64)> where S: RandomSource {
+        let unigram = self.unigram();
+        if unigram.items.is_empty() {
+            return None;
+        }
+        let fits = |index: &usize| {
+            let item = &unigram.items[*index];
+            allowed.iter().all(|allowed| allowed(item))
+                && self.chain.contains_key(&advance(node, &Some(item.clone())))
+        };
+        // most items usually fit, so a few draws from every item almost always
+        // find one without looking through them all
+        for _ in 0 .. BACKOFF_DRAWS {
+            let index = unigram.choose(random_f64(rng));
+            if fits(&index) {
+                return Some((&unigram.items[index], unigram.probability(index)));
+            }
+        }
+        let total = (0 .. unigram.items.len())
+            .filter(&fits)
+            .map(|index| unigram.weight(index))
+            .sum::<f64>();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut choice = random_f64(rng) * total;
+        let mut chosen = None;
+        for index in (0 .. unigram.items.len()).filter(&fits) {
+            chosen = Some(index);
+            if choice < unigram.weight(index) {
+                break;
+            }
+            choice -= unigram.weight(index);
+        }
+        chosen.map(|index| (&unigram.items[index], unigram.probability(index)))
+    }
+
+    /// Generates a string of items with no maximum limit.
+    /// This is equivalent to `generate_limit(-1)`.
+    pub fn generate(&self) -> Vec<T> {
+        self.generate_limit(-1)
+    }
+
+    /// Generates items one at a time, starting from a random node like
+    /// `generate`. The iterator ends once generation does, so it's infinite
+    /// only if the chain can loop forever; `take` limits how many items are
+    /// generated.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 2, 1, 2, 3, 4, 3, 2, 1]);
+    /// let sequence = chain.iter_generate().take(50).collect::<Vec<_>>();
+    /// assert!(sequence.len() <= 50);
+    /// ```
+    pub fn iter_generate(&self) -> GenerateIter<'_, T, W, H> {
+        let mut rng = unseeded();
+        let (start, curs) = match self.choose_random_node(&mut rng) {
+            Some(node) => (node.iter().flatten().cloned().collect(), node.clone()),
+            None => (vec![], vec![None; self.order]),
+        };
+        GenerateIter {
+            start: start.into_iter(),
+            walk: Walk::new(self, curs, None, Sampling::default()),
+            rng,
+        }
+    }
+
+    /// Generates a string of items, based on the training, of up to N items.
+    /// Specifying a maximum of -1 allows any arbitrary size of list.
+    ///
+    /// The maximum counts every item returned, including the items of the
+    /// node generation starts from, so the result is never longer than `max`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(3);
+    /// chain.train(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    /// assert!(chain.generate_limit(2).len() <= 2);
+    /// ```
+    pub fn generate_limit(&self, max: isize) -> Vec<T> {
+        self.generate_inner(max, None, &mut unseeded()).0
+    }
+
+    /// Generates a string of items that continues on from `prefix`, returning
+    /// the prefix followed by the generated items. Only the last `order` items
+    /// of the prefix are used to pick what comes next; a shorter prefix is
+    /// treated as the beginning of a sequence.
+    ///
+    /// If the chain has nothing that can follow the prefix, the prefix is
+    /// returned unchanged, unless the chain backs off at dead ends (see
+    /// `with_unigram_backoff`).
+    /// An empty prefix behaves like `generate_limit`.
+    /// Like `generate_limit`, the maximum counts the items of the prefix, so a
+    /// prefix longer than `max` is cut short to `max` items.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// assert_eq!(chain.generate_from(&[5, 2], -1), vec![5, 2, 3]);
+    /// assert_eq!(chain.generate_from(&[3, 9], -1), vec![3, 9]);
+    /// ```
+    pub fn generate_from(&self, prefix: &[T], max: isize) -> Vec<T> {
+        self.generate_from_sampled(prefix, max, Sampling::default())
+    }
+
+    /// Generates a string of items that continues on from `prefix` like
+    /// `generate_from`, choosing links with the given sampling.
+    fn generate_from_sampled(&self, prefix: &[T], max: isize, sampling: Sampling) -> Vec<T> {
+        if prefix.is_empty() {
+            return self.generate_sampled(sampling, max);
+        }
+        let curs = self.cursor_from(prefix);
+        let mut result = prefix.to_vec();
+        if max > 0 {
+            result.truncate(max as usize);
+        }
+        self.extend_from_node(curs, result, max, None, sampling, &mut unseeded()).0
+    }
+
+    /// Generates a string of items from a chain trained with `train_reverse`.
+    /// Items are generated from the end of the string back to the beginning,
+    /// and the result is flipped so that it reads in the original order. Like
+    /// `generate`, it starts from a random node, so it ends wherever that node
+    /// is, and begins where a trained string began.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_reverse(vec![1, 2, 3]);
+    /// assert_eq!(chain.generate_reverse().first(), Some(&1));
+    /// ```
+    pub fn generate_reverse(&self) -> Vec<T> {
+        let mut result = self.generate();
+        result.reverse();
+        result
+    }
+
+    /// Generates a string of items that continues on from `prefix` like
+    /// `generate_from`, always choosing the most likely item with
+    /// `most_likely_next` instead of choosing at random. The same chain and
+    /// prefix always give the same result, which makes it useful for previews.
+    ///
+    /// Generation stops once the current node can only end the sequence, or
+    /// at `max` items. Following the most likely items through a loop would
+    /// go on forever, so without a limit, generation also stops just before
+    /// it would come back around to a node it has already been through. The
+    /// prefix counts towards `max`, and is cut short if it's longer.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 4])
+    ///     .train(vec![2, 3, 5]);
+    /// assert_eq!(chain.generate_greedy(&[], -1), vec![1, 2, 3, 4]);
+    /// assert_eq!(chain.generate_greedy(&[2], 2), vec![2, 3]);
+    /// ```
+    pub fn generate_greedy(&self, prefix: &[T], max: isize) -> Vec<T>
+        where T: Ord {
+        let mut result = prefix.to_vec();
+        if max > 0 {
+            result.truncate(max as usize);
+        }
+        let mut curs = self.cursor_from(prefix);
+        let mut visited = HashSet::new();
+        visited.insert(curs.clone());
+        while max <= 0 || (result.len() as isize) < max {
+            let next = match self.most_likely_next(&curs) {
+                Some(next) => Some(next.clone()),
+                None => break,
+            };
+            curs = advance(&curs, &next);
+            if max <= 0 && !visited.insert(curs.clone()) {
+                break;
+            }
+            result.extend(next);
+        }
+        result
+    }
+
+    /// Generates a string of at least `min` and at most `max` items.
+    ///
+    /// When generation ends before reaching `min` items, the result is thrown
+    /// away and generation starts over from a fresh random node. A chain
+    /// might never produce `min` items, so after 100 attempts this gives up
+    /// and returns the longest result it generated. A `max` of 0 means there
+    /// is no maximum.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2])
+    ///     .train(vec![3, 4, 5, 6, 7]);
+    /// let sequence = chain.generate_bounded(3, 4);
+    /// assert!((3 ..= 4).contains(&sequence.len()));
+    /// ```
+    pub fn generate_bounded(&self, min: usize, max: usize) -> Vec<T> {
+        const MAX_ATTEMPTS: usize = 100;
+        let mut rng = unseeded();
+        let mut best = Vec::new();
+        for _ in 0 .. MAX_ATTEMPTS {
+            let (result, _, _) = self.generate_inner(max as isize, None, &mut rng);
+            if result.len() >= min {
+                return result;
+            }
+            if result.len() > best.len() {
+                best = result;
+            }
+        }
+        best
+    }
+
+    /// Generates up to `n` different strings of items, each of up to `max`
+    /// items like `generate_limit`, in the order they were generated.
+    ///
+    /// A small chain may only be able to produce a few different strings, so
+    /// this gives up after `10 * n` attempts and returns the distinct strings
+    /// it has found so far. Empty strings are never returned.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 4])
+    ///     .train(vec![1, 3, 2, 4]);
+    /// let sequences = chain.generate_distinct(3, -1);
+    /// assert!(!sequences.is_empty() && sequences.len() <= 3);
+    /// assert!(sequences.iter().all(|s| sequences.iter().filter(|&t| t == s).count() == 1));
+    /// ```
+    pub fn generate_distinct(&self, n: usize, max: isize) -> Vec<Vec<T>> {
+        const ATTEMPTS_PER_RESULT: usize = 10;
+        let mut rng = unseeded();
+        let mut seen = HashSet::new();
+        let mut results = Vec::new();
+        for _ in 0 .. n.saturating_mul(ATTEMPTS_PER_RESULT) {
+            if results.len() == n {
+                break;
+            }
+            let (result, _, _) = self.generate_inner(max, None, &mut rng);
+            if !result.is_empty() && seen.insert(result.clone()) {
+                results.push(result);
+            }
+        }
+        results
+    }
+
+    /// Generates a string of items like `generate_limit`, with `temperature`
+    /// controlling how adventurous the choice of each item is.
+    ///
+    /// Link weights are raised to the power of `1.0 / temperature` before
+    /// choosing, so a temperature of 1.0 chooses exactly like `generate_limit`,
+    /// lower temperatures favor the heaviest links, and higher temperatures
+    /// make every link more equally likely. A temperature of 0.0 always
+    /// chooses the heaviest link. The starting node is still chosen at random.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 2, 1, 2, 3, 4, 3, 2, 1]);
+    /// let sequence = chain.generate_with_temperature(0.5, 20);
+    /// ```
+    pub fn generate_with_temperature(&self, temperature: f64, max: isize) -> Vec<T> {
+        let mut rng = unseeded();
+        match self.choose_random_node(&mut rng) {
+            Some(n) => self.generate_from_node(n.clone(), max, None, Sampling::tempered(temperature), &mut rng).0,
+            None => vec![],
+        }
+    }
+
+    /// Generates a string of items like `generate_limit`, only ever choosing
+    /// from the `k` heaviest links of each node. The chosen links are weighted
+    /// among themselves as usual, so rare transitions can't lead generation
+    /// astray. Links of equal weight are ranked in a fixed order, so which
+    /// ones make the cut doesn't depend on how they happen to be stored.
+    ///
+    /// With a `k` of 1, this always follows the heaviest link, which may be
+    /// the one that ends the sequence. A `k` of 0 means every link can be
+    /// chosen. The starting node is still chosen at random.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![1, 2, 3])
+    ///     .train(vec![1, 2, 4]);
+    /// // only the heaviest link is ever followed
+    /// let sequence = chain.generate_top_k(1, -1);
+    /// assert_eq!(chain.generate_greedy(&sequence[.. 1], -1), sequence);
+    /// ```
+    pub fn generate_top_k(&self, k: usize, max: isize) -> Vec<T> {
+        let sampling = Sampling {
+            top_k: k,
+            ..Sampling::default()
+        };
+        self.generate_sampled(sampling, max)
+    }
+
+    /// Generates a string of items like `generate_limit`, with nucleus (top-p)
+    /// sampling: at each node, only the fewest heaviest links whose
+    /// probabilities add up to at least `p` can be chosen, weighted among
+    /// themselves as usual. Unlike `generate_top_k`, the number of choices
+    /// adapts to each node, so a node with one dominant link sticks to it and
+    /// a node with many similar links keeps them all.
+    ///
+    /// A `p` of 1.0 or more means every link can be chosen, and a `p` of 0.0
+    /// or less only ever follows the heaviest link. Ties are ranked like in
+    /// `generate_top_k`. The starting node is still chosen at random.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// for _ in 0 .. 9 {
+    ///     chain.train(vec![1, 2]);
+    /// }
+    /// chain.train(vec![1, 3]);
+    /// // 2 follows 1 90% of the time, so 3 is left out
+    /// let sequence = chain.generate_top_p(0.9, -1);
+    /// assert!(!sequence.contains(&3) || sequence == vec![3]);
+    /// ```
+    pub fn generate_top_p(&self, p: f64, max: isize) -> Vec<T> {
+        let sampling = Sampling {
+            top_p: p,
+            ..Sampling::default()
+        };
+        self.generate_sampled(sampling, max)
+    }
+
+    /// Generates a string of items from a random node, choosing links with the
+    /// given sampling.
+    fn generate_sampled(&self, sampling: Sampling, max: isize) -> Vec<T> {
+        let mut rng = unseeded();
+        match self.choose_random_node(&mut rng) {
+            Some(n) => self.generate_from_node(n.clone(), max, None, sampling, &mut rng).0,
+            None => vec![],
+        }
+    }
+
+    /// Generates a string of items like `generate_limit`, using `rng` for
+    /// every random choice. Given the same chain and an RNG with the same
+    /// seed, the output is the same every time.
+    /// # Examples
+    /// ```
+    /// extern crate rand;
+    /// # extern crate markov_chain;
+    /// use markov_chain::Chain;
+    /// use rand::{SeedableRng, StdRng};
+    /// # fn main() {
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 2, 1, 2, 3, 4, 3, 2, 1]);
+    /// let first = chain.generate_with_rng(&mut StdRng::from_seed(&[42][..]), -1);
+    /// let second = chain.generate_with_rng(&mut StdRng::from_seed(&[42][..]), -1);
+    /// assert_eq!(first, second);
+    /// # }
+    /// ```
+    pub fn generate_with_rng<R>(&self, rng: &mut R, max: isize) -> Vec<T>
+        where R: Rng {
+        self.generate_inner(max, None, rng).0
+    }
+
+    /// Chooses the item to follow `node` at random, weighted by the link
+    /// weights like every step of generation, along with the probability that
+    /// it was chosen. The item is `None` when the terminal link was chosen.
+    /// This is `None` if the node is not in the chain or has no links.
+    ///
+    /// This is a single step of generation, for building custom generation
+    /// loops or inspecting each choice as it is made.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 1, 3, 1, 2]);
+    /// let mut rng = rand::thread_rng();
+    /// match chain.sample_next(&[Some(1)], &mut rng) {
+    ///     Some((Some(2), p)) => assert_eq!(p, 2.0 / 3.0),
+    ///     Some((Some(3), p)) => assert_eq!(p, 1.0 / 3.0),
+    ///     _ => unreachable!(),
+    /// }
+    /// assert_eq!(chain.sample_next(&[Some(4)], &mut rng), None);
+    /// ```
+    pub fn sample_next<R>(&self, node: &[Option<T>], rng: &mut R) -> Option<(Option<T>, f64)>
+        where R: Rng {
+        if node.len() != self.order {
+            return None;
+        }
+        self.choose_random_link(node, None, rng)
+            .map(|(next, p)| (next.clone(), p))
+    }
+
+    /// Generates a string of items like `generate_limit`, additionally
+    /// reporting how generation came to an end.
+    ///
+    /// Reaching a node that has no entry in the chain (which can happen after
+    /// pruning) is treated as a clean terminal, and reported as
+    /// `Termination::DeadEnd` so callers can keep track of how often it
+    /// happens.
+    /// # Examples
+    /// ```
+    /// use markov_chain::{Chain, Termination};
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// let (_, termination) = chain.generate_graceful(-1);
+    /// assert_eq!(termination, Termination::Terminal);
+    /// ```
+    pub fn generate_graceful(&self, max: isize) -> (Vec<T>, Termination) {
+        let (result, termination, _) = self.generate_inner(max, None, &mut unseeded());
+        (result, termination)
+    }
+
+    /// Generates a string of items like `generate_limit`, along with the
+    /// natural log of the probability of having generated it.
+    ///
+    /// The score is the sum of `ln(weight / total)` over every transition that
+    /// was taken, including the final terminal link if generation ended on one.
+    /// The starting node is picked at random and its items are
+    /// treated as given, so the choice of start does not contribute to the
+    /// score.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3]);
+    /// let (_, score) = chain.generate_scored(-1);
+    /// assert!(score <= 0.0);
+    /// ```
+    pub fn generate_scored(&self, max: isize) -> (Vec<T>, f64) {
+        let (result, _, score) = self.generate_inner(max, None, &mut unseeded());
+        (result, score)
+    }
+
+    /// Generates a string of items like `generate_limit`, where every item
+    /// must satisfy `allowed`.
+    ///
+    /// At each step, only the links to allowed items (and the terminal link)
+    /// are considered, weighted as usual. When a node has no allowed
+    /// continuation, generation terminates there. The starting node is chosen
+    /// among the nodes made up entirely of allowed items, and if there are
+    /// none, the result is empty.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 4])
+    ///     .train(vec![1, 3, 4, 2]);
+    /// let sequence = chain.generate_filtered(-1, |&x| x != 3);
+    /// assert!(!sequence.contains(&3));
+    /// ```
+    pub fn generate_filtered<F>(&self, max: isize, allowed: F) -> Vec<T>
+        where F: Fn(&T) -> bool {
+        self.generate_inner(max, Some(&allowed), &mut unseeded()).0
+    }
+
+    /// Generates a string of items like `generate_filtered`, never generating
+    /// any item in `avoid` and using `rng` for every random choice. At each
+    /// step, links to avoided items are left out and the rest are weighted
+    /// among themselves; when only avoided items can follow, generation ends
+    /// there. Unlike `restrict_vocabulary`, the chain isn't changed, so each
+    /// call can avoid something different.
+    /// # Examples
+    /// ```
+    /// extern crate rand;
+    /// # extern crate markov_chain;
+    /// use markov_chain::Chain;
+    /// use std::collections::HashSet;
+    /// # fn main() {
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 4])
+    ///     .train(vec![1, 3, 2, 4]);
+    /// let avoid = vec![3].into_iter().collect::<HashSet<_>>();
+    /// let sequence = chain.generate_avoiding(&avoid, &mut rand::thread_rng(), -1);
+    /// assert!(!sequence.contains(&3));
+    /// # }
+    /// ```
+    pub fn generate_avoiding<R>(&self, avoid: &HashSet<T>, rng: &mut R, max: isize) -> Vec<T>
+        where R: Rng {
+        let allowed = |item: &T| !avoid.contains(item);
+        self.generate_inner(max, Some(&allowed), rng).0
+    }
+
+    /// Generates a string of items like `generate_limit`, drawing random
+    /// numbers from `source` instead of the thread's RNG.
+    /// # Examples
+    /// ```
+    /// use markov_chain::{Chain, RandomSource};
+    ///
+    /// struct Counter(u32);
+    ///
+    /// impl RandomSource for Counter {
+    ///     fn next_u32(&mut self) -> u32 {
+    ///         self.0 = self.0.wrapping_add(0x9e3779b9);
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3, 2, 1]);
+    /// let sequence = chain.generate_with_source(&mut Counter(0), -1);
+    /// ```
+    pub fn generate_with_source<S>(&self, source: &mut S, max: isize) -> Vec<T>
+        where S: RandomSource {
+        self.generate_inner(max, None, source).0
+    }
+
+    /// Generates a string of items, starting from the opening of a trained
+    /// sequence and preferring sequences that were trained recently.
+    ///
+    /// Each opening is weighted by `decay` raised to the number of sequences
+    /// trained since it was last seen, so a `decay` of `0.9` makes an opening
+    /// from ten sequences ago about a third as likely as the latest one. A
+    /// `decay` of `1.0` picks uniformly among all openings. This is useful for
+    /// a continuously trained chain, like a chatbot, to talk about what it has
+    /// heard recently.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![1, 2, 3])
+    ///     .train(vec![4, 5, 6]);
+    /// let sequence = chain.generate_recent_start(0.5);
+    /// ```
+    pub fn generate_recent_start(&self, decay: f64) -> Vec<T> {
+        let openings = self.recency.iter()
+            .filter(|&(node, _)| self.chain.contains_key(node))
+            .collect::<Vec<_>>();
+        if openings.is_empty() {
+            return vec![];
+        }
+        let weights = openings.iter()
+            .map(|&(_, &time)| decay.powf((self.clock - time) as f64))
+            .collect::<Vec<_>>();
+        let mut rng = unseeded();
+        let (node, _) = openings[choose_weighted_index(&weights, &mut rng)];
+        self.generate_from_node(node.clone(), -1, None, Sampling::default(), &mut rng).0
+    }
+
+    /// Generates a string of items by alternating between two chains of the
+    /// same order, taking `switch_every` items from one chain before switching
+    /// to the other. Generation starts at a random node of `a`, or of `b` if
+    /// `a` is empty, and a `switch_every` of 0 never switches.
+    ///
+    /// When the chain whose turn it is has never seen the current node, the
+    /// other chain is tried before generation ends.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut a = Chain::new(1);
+    /// let mut b = Chain::new(1);
+    /// a.train(vec![1, 2, 3, 4]);
+    /// b.train(vec![2, 4, 3, 1]);
+    /// let sequence = Chain::generate_alternating(&a, &b, 2, 10);
+    /// ```
+    pub fn generate_alternating(a: &Self, b: &Self, switch_every: usize, max: isize) -> Vec<T> {
+        assert_eq!(a.order, b.order, "orders must be equal in order to alternate markov chains");
+        let mut rng = unseeded();
+        let start = match a.choose_random_node(&mut rng) {
+            Some(n) => Some(n),
+            None => b.choose_random_node(&mut rng),
+        };
+        let mut curs = match start {
+            Some(n) => n.clone(),
+            None => return vec![],
+        };
+
+        let mut result = curs.iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<T>>();
+        if max > 0 {
+            result.truncate(max as usize);
+        }
+        let chains = [a, b];
+        let mut current = 0;
+        let mut taken = 0;
+        while max <= 0 || (result.len() as isize) < max {
+            if switch_every > 0 && taken == switch_every {
+                current = 1 - current;
+                taken = 0;
+            }
+            let next = match chains[current].choose_random_link(&curs, None, &mut rng) {
+                Some((next, _)) => next,
+                None => match chains[1 - current].choose_random_link(&curs, None, &mut rng) {
+                    Some((next, _)) => next,
+                    None => return result,
+                },
+            };
+            match *next {
+                Some(ref next) => {
+                    result.push(next.clone());
+                    curs.push(Some(next.clone()));
+                    curs.remove(0);
+                    taken += 1;
+                },
+                None => return result,
+            }
+        }
+        result
+    }
+
+    /// Generates a string of items, returning the items, how generation ended,
+    /// and the log-probability of the transitions taken. If `allowed` is
+    /// given, only items it accepts are generated.
+    fn generate_inner<S>(&self, max: isize, allowed: Option<&dyn Fn(&T) -> bool>, rng: &mut S)
+        -> (Vec<T>, Termination, f64) where S: RandomSource {
+        let start = match allowed {
+            Some(allowed) => self.choose_random_node_filtered(allowed, rng),
+            None => self.choose_random_node(rng),
+        };
+        match start {
+            Some(n) => self.generate_from_node(n.clone(), max, allowed, Sampling::default(), rng),
+            None => (vec![], Termination::DeadEnd, 0.0),
+        }
+    }
+
+    /// Generates a string of items starting at the given node, like
+    /// `generate_inner`, choosing links with the given sampling.
+    fn generate_from_node<S>(&self, curs: Node<T>, max: isize, allowed: Option<&dyn Fn(&T) -> bool>,
+                             sampling: Sampling, rng: &mut S) -> (Vec<T>, Termination, f64)
+        where S: RandomSource {
+        // a node with `None` padding (only chosen when the chain has no
+        // complete nodes) just contributes the items it has
+        let mut result = curs.iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<T>>();
+        if max > 0 {
+            result.truncate(max as usize);
+        }
+        self.extend_from_node(curs, result, max, allowed, sampling, rng)
+    }
+
+    /// Generates items onto the end of `result`, starting at the given node,
+    /// like `generate_from_node`.
+    fn extend_from_node<S>(&self, curs: Node<T>, mut result: Vec<T>, max: isize,
+                           allowed: Option<&dyn Fn(&T) -> bool>, sampling: Sampling, rng: &mut S)
+        -> (Vec<T>, Termination, f64) where S: RandomSource {
+        let mut walk = Walk::new(self, curs, allowed, sampling);
+        // check the limit before each item, since `result` may already be full
+        while max <= 0 || (result.len() as isize) < max {
+            match walk.next(rng) {
+                Some(next) => result.push(next),
+                None => {
+                    let termination = walk.termination.unwrap_or(Termination::DeadEnd);
+                    return (result, termination, walk.score);
+                },
+            }
+        }
+        (result, Termination::Limit, walk.score)
+    }
+
+    /// Chooses a random link from a node, weighted by the link weights, along
+    /// with the probability of it having been chosen. If `allowed` is given,
+    /// only the terminal link and links to items it accepts are considered.
+    ///
+    /// This yields `None` if the node is not in the chain, isn't as long as the
+    /// order of the chain or has no links to choose from, and
+    /// `Some((&None, _))` if the terminal link was chosen.
+    fn choose_random_link<S>(&self, node: &[Option<T>], allowed: Option<&dyn Fn(&T) -> bool>, rng: &mut S)
+        -> Option<(&Option<T>, f64)> where S: RandomSource {
+        self.choose_random_link_sampled(node, allowed, Sampling::default(), rng)
+    }
+
+    /// Chooses a random link from a node like `choose_random_link`, narrowing
+    /// down and weighting the links as `sampling` says.
+    fn choose_random_link_sampled<S>(&self, node: &[Option<T>], allowed: Option<&dyn Fn(&T) -> bool>,
+                                     sampling: Sampling, rng: &mut S) -> Option<(&Option<T>, f64)>
+        where S: RandomSource {
+        if node.len() != self.order {
+            return None;
+        }
+        let link = self.chain.get(node)?;
+        let mut candidates = link.iter()
+            .filter(|&(k, _)| match (k, allowed) {
+                (Some(item), Some(allowed)) => allowed(item),
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        fix_order(&mut candidates, rng, |&(next, _)| next);
+        if sampling.top_k > 0 || sampling.top_p < 1.0 {
+            // the sort is stable, so ties stay in the order of their hashes when
+            // they were fixed
+            candidates.sort_by(|&(_, a), &(_, b)| b.partial_cmp(a).unwrap_or(cmp::Ordering::Equal));
+        }
+        if sampling.top_k > 0 {
+            candidates.truncate(sampling.top_k);
+        }
+        if sampling.top_p < 1.0 {
+            let total = candidates.iter()
+                .map(|&(_, weight)| weight.to_f64())
+                .sum::<f64>();
+            // keep the heaviest links until their probability reaches `top_p`
+            let mut cumulative = 0.0;
+            let mut keep = 0;
+            for &(_, weight) in &candidates {
+                keep += 1;
+                cumulative += weight.to_f64() / total;
+                if cumulative >= sampling.top_p {
+                    break;
+                }
+            }
+            candidates.truncate(keep);
+        }
+        let temperature = sampling.temperature;
+        let total = candidates.iter()
+            .map(|&(_, weight)| weight.to_f64())
+            .sum::<f64>();
+        if total <= 0.0 {
+            return None;
+        }
+        // scale by the heaviest weight first, so that low temperatures
+        // don't overflow
+        let heaviest = candidates.iter()
+            .map(|&(_, weight)| weight.to_f64())
+            .fold(0.0, f64::max);
+        if temperature <= 0.0 {
+            return candidates.iter()
+                .find(|&&(_, weight)| weight.to_f64() == heaviest)
+                .map(|&(next, _)| (next, 1.0));
+        }
+        if temperature != 1.0 {
+            let weights = candidates.iter()
+                .map(|&(_, weight)| (weight.to_f64() / heaviest).powf(1.0 / temperature))
+                .collect::<Vec<_>>();
+            let total = weights.iter().sum::<f64>();
+            let index = choose_weighted_index(&weights, rng);
+            return Some((candidates[index].0, weights[index] / total));
+        }
+        let mut choice = random_f64(rng) * total;
+        for &(next, weight) in &candidates {
+            let weight = weight.to_f64();
+            if choice < weight {
+                return Some((next, weight / total));
+            }
+            choice -= weight;
+        }
+        // floating point error may leave a sliver past the last candidate
+        candidates.iter()
+            .rev()
+            .find(|&&(_, weight)| weight.to_f64() > 0.0)
+            .map(|&(next, weight)| (next, weight.to_f64() / total))
+    }
+
+    /// Chooses a random node made up entirely of items that are `allowed`,
+    /// like `choose_random_node`.
+    fn choose_random_node_filtered<S>(&self, allowed: &dyn Fn(&T) -> bool, rng: &mut S) -> Option<&Node<T>>
+        where S: RandomSource {
+        let nodes = self.chain.keys()
+            .filter(|node| node.iter().flatten().all(allowed))
+            .collect::<Vec<_>>();
+        choose_start(nodes, rng)
+    }
+
+    /// Chooses a random node to start generating from. Complete nodes, which
+    /// have no `None` padding, are preferred; a chain trained only on
+    /// sequences shorter than its order has none, so any node is chosen then.
+    fn choose_random_node<S>(&self, rng: &mut S) -> Option<&Node<T>> where S: RandomSource {
+        if !self.starts.is_empty() {
+            if !rng.is_repeatable() {
+                return Some(&self.starts[random_index(rng, self.starts.len())]);
+            }
+            let mut starts = self.starts.iter()
+                .collect::<Vec<_>>();
+            fix_order(&mut starts, rng, |&node| node);
+            return Some(starts[random_index(rng, starts.len())]);
+        }
+        let nodes = self.chain.keys()
+            .collect::<Vec<_>>();
+        choose_start(nodes, rng)
+    }
+}
+
+/// An iterator that generates items from a chain one at a time. This is
+/// created by `Chain::iter_generate`.
+pub struct GenerateIter<'a, T, W = u32, H = RandomState>
+    where T: Clone + Chainable + 'a, W: Weight + 'a, H: BuildHasher + 'a {
+    /// The items of the starting node, which come first.
+    start: vec::IntoIter<T>,
+    walk: Walk<'a, T, W, H>,
+    rng: Unseeded,
+}
+
+impl<'a, T, W, H> Iterator for GenerateIter<'a, T, W, H>
+    where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self.start.next() {
+            Some(item) => Some(item),
+            None => self.walk.next(&mut self.rng),
+        }
+    }
+}
+
+/// How the links of a node are narrowed down and weighted before one of them
+/// is chosen.
+#[derive(Clone, Copy, Debug)]
+struct Sampling {
+    /// Link weights are raised to the power of `1.0 / temperature`. A
+    /// temperature of 0.0 or less always chooses the heaviest link.
+    temperature: f64,
+    /// Only the `top_k` heaviest links can be chosen, or every link if this
+    /// is 0.
+    top_k: usize,
+    /// Only the fewest heaviest links whose probabilities add up to at least
+    /// `top_p` can be chosen. This is 1.0 to choose from every link.
+    top_p: f64,
+}
+
+impl Sampling {
+    /// Chooses from every link at the given temperature.
+    fn tempered(temperature: f64) -> Self {
+        Sampling {
+            temperature,
+            top_k: 0,
+            top_p: 1.0,
+        }
+    }
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling::tempered(1.0)
+    }
+}
+
+/// A walk through a chain from some node, choosing one link at a time. This
+/// is the loop that every kind of generation is built on.
+struct Walk<'a, T, W, H> where T: Clone + Chainable + 'a, W: Weight + 'a, H: BuildHasher + 'a {
+    chain: &'a Chain<T, W, H>,
+    curs: Node<T>,
+    allowed: Option<&'a dyn Fn(&T) -> bool>,
+    sampling: Sampling,
+    /// How the walk ended, once it has.
+    termination: Option<Termination>,
+    /// The log-probability of the links chosen so far.
+    score: f64,
+}
+
+impl<'a, T, W, H> Walk<'a, T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn new(chain: &'a Chain<T, W, H>, curs: Node<T>, allowed: Option<&'a dyn Fn(&T) -> bool>,
+           sampling: Sampling) -> Self {
+        Walk {
+            chain,
+            curs,
+            allowed,
+            sampling,
+            termination: None,
+            score: 0.0,
+        }
+    }
+
+    /// Chooses the next item, or ends the walk.
+    fn next<S>(&mut self, rng: &mut S) -> Option<T> where S: RandomSource {
+        if self.termination.is_some() {
+            return None;
+        }
+        let next = match self.chain.choose_random_link_sampled(&self.curs, self.allowed, self.sampling, rng) {
+            Some((next, p)) => {
+                self.score += p.ln();
+                next.clone()
+            },
+            None if self.chain.unigram_backoff => match self.chain.choose_unigram(&self.curs, self.allowed, rng) {
+                Some((next, p)) => {
+                    self.score += p.ln();
+                    Some(next.clone())
+                },
+                None => {
+                    self.termination = Some(Termination::DeadEnd);
+                    return None;
+                },
+            },
+            None => {
+                self.termination = Some(Termination::DeadEnd);
+                return None;
+            },
+        };
+        match next {
+            Some(next) => {
+                self.curs.push(Some(next.clone()));
+                self.curs.remove(0);
+                Some(next)
+            },
+            None => {
+                self.termination = Some(Termination::Terminal);
+                None
+            },
+        }
+    }
+}
+
+/// How many times an item is drawn from every trained item when backing off,
+/// before looking through them all for one that fits.
+const BACKOFF_DRAWS: usize = 8;
+
+/// How often each item was trained, laid out so that an item can be chosen by
+/// weight without looking through all of them.
+#[derive(Clone, Debug)]
+struct Unigram<T> {
+    /// Every trained item, in a fixed order, so that seeded generation backs
+    /// off the same way every time.
+    items: Vec<T>,
+    /// The total weight of each item and every item before it.
+    cumulative: Vec<f64>,
+}
+
+impl<T> Unigram<T> where T: Hash {
+    fn new<W: Weight>(totals: HashMap<T, W>) -> Self {
+        let mut totals = totals.into_iter()
+            .filter(|&(_, weight)| weight > W::zero())
+            .collect::<Vec<_>>();
+        totals.sort_by_cached_key(|(item, _)| stable_hash(item));
+        let mut sum = 0.0;
+        let cumulative = totals.iter()
+            .map(|&(_, weight)| {
+                sum += weight.to_f64();
+                sum
+            })
+            .collect();
+        Unigram {
+            items: totals.into_iter().map(|(item, _)| item).collect(),
+            cumulative,
+        }
+    }
+
+    /// Gets the weight of the item at `index`.
+    fn weight(&self, index: usize) -> f64 {
+        match index {
+            0 => self.cumulative[0],
+            _ => self.cumulative[index] - self.cumulative[index - 1],
+        }
+    }
+
+    /// Gets how likely the item at `index` is to be chosen out of every item.
+    fn probability(&self, index: usize) -> f64 {
+        self.weight(index) / self.cumulative[self.cumulative.len() - 1]
+    }
+
+    /// Gets the index of the item that a `fraction` of the way through the
+    /// total weight falls on. There must be at least one item.
+    fn choose(&self, fraction: f64) -> usize {
+        let choice = fraction * self.cumulative[self.cumulative.len() - 1];
+        cmp::min(self.cumulative.partition_point(|&sum| sum <= choice), self.items.len() - 1)
+    }
+}
+
+/// Creates an empty chain of order 1, the smallest order, like
+/// `Chain::new(1)`. Use `new` for any other order.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let chain = Chain::<u32>::default();
+/// assert_eq!(chain.order(), 1);
+/// ```
+impl<T, W, H> Default for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn default() -> Self {
+        Chain::with_weights(1)
+    }
+}
+
+/// Collects sequences into a new chain of order 1, training it on each one.
+/// To collect into a chain of a different order, create it with `new` and use
+/// `extend` instead.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let chain: Chain<u32> = vec![vec![1, 2, 3], vec![3, 2, 1]].into_iter().collect();
+/// assert_eq!(chain.order(), 1);
+/// ```
+impl<T, W, H> FromIterator<Vec<T>> for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn from_iter<I>(iter: I) -> Self where I: IntoIterator<Item = Vec<T>> {
+        let mut chain = Chain::with_weights(1);
+        chain.extend(iter);
+        chain
+    }
+}
+
+/// Trains the chain on each sequence.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let mut chain = Chain::new(2);
+/// chain.extend(vec![vec![1, 2, 3], vec![3, 2, 1]]);
+/// ```
+impl<T, W, H> Extend<Vec<T>> for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn extend<I>(&mut self, iter: I) where I: IntoIterator<Item = Vec<T>> {
+        for string in iter {
+            self.train(string);
+        }
+    }
+}
+
+/// Merges two chains, like `merge`.
+///
+/// # Panics
+/// Panics if the orders of the chains differ.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let mut chain1 = Chain::new(1);
+/// let mut chain2 = chain1.clone();
+/// chain1.train(vec![1, 2, 3]);
+/// chain2.train(vec![2, 3, 4]);
+/// let combined = chain1 + &chain2;
+/// assert_eq!(combined.transition_weight(&[Some(2)], &Some(3)), 2);
+/// ```
+impl<T, W, H> Add<&Chain<T, W, H>> for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    type Output = Self;
+
+    fn add(mut self, other: &Self) -> Self {
+        self.merge(other);
+        self
+    }
+}
+
+/// Merges two chains, like `merge`.
+///
+/// # Panics
+/// Panics if the orders of the chains differ.
+impl<T, W, H> Add for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self + &other
+    }
+}
+
+/// Merges another chain into this one, like `merge`.
+///
+/// # Panics
+/// Panics if the orders of the chains differ.
+/// # Examples
+/// ```
+/// use markov_chain::Chain;
+/// let mut total = Chain::new(1);
+/// let mut chain = total.clone();
+/// chain.train(vec![1, 2, 3]);
+/// total += &chain;
+/// total += chain;
+/// assert_eq!(total.transition_weight(&[Some(1)], &Some(2)), 2);
+/// ```
+impl<T, W, H> AddAssign<&Chain<T, W, H>> for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn add_assign(&mut self, other: &Self) {
+        self.merge(other);
+    }
+}
+
+/// Merges another chain into this one, like `merge`.
+///
+/// # Panics
+/// Panics if the orders of the chains differ.
+impl<T, W, H> AddAssign for Chain<T, W, H> where T: Clone + Chainable, W: Weight, H: BuildHasher + Clone + Default {
+    fn add_assign(&mut self, other: Self) {
+        self.merge(&other);
+    }
+}
+
+/// Parallel training, enabled by the `parallel` feature.
+#[cfg(feature = "parallel")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + Send + Sync,
+          W: Weight + Send,
+          H: BuildHasher + Clone + Default + Send {
+    /// Trains the chain on many sequences at once, using every core. The
+    /// sequences are split between threads, which each train a partial chain
+    /// of their own, and the partial chains are then merged into this one.
+    /// The result is the same as training each sequence with `train`.
+    ///
+    /// This is much faster than `train` for a large corpus of sequences, at
+    /// the cost of holding a partial chain per thread in memory until they
+    /// are merged. Sequences are trained in no particular order, so the
+    /// training recency used by `generate_recent_start` is only approximate.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_par(vec![vec![1, 2, 3], vec![2, 3, 4]]);
+    /// assert_eq!(chain.transition_weight(&[Some(2)], &Some(3)), 2);
+    /// ```
+    pub fn train_par<I>(&mut self, sequences: I) -> &mut Self
+        where I: IntoParallelIterator<Item = Vec<T>> {
+        let order = self.order;
+        let partial = sequences.into_par_iter()
+            .fold(|| Chain::with_weights(order), |mut chain: Self, sequence| {
+                chain.train(sequence);
+                chain
+            })
+            .reduce(|| Chain::with_weights(order), |mut a, b| {
+                a.merge(&b);
+                a
+            });
+        self.merge(&partial)
+    }
+}
+
+/// bincode serialization, enabled by the `bincode` feature. bincode is more
+/// compact and faster to read than the other formats.
+#[cfg(feature = "bincode")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to bincode.
+    pub fn to_bincode(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    /// Deserializes a chain from bincode.
+    pub fn from_bincode(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// CBOR serialization, enabled by the `serde_cbor` feature.
+#[cfg(feature = "serde_cbor")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to CBOR.
+    pub fn to_cbor(&self) -> serde_cbor::Result<Vec<u8>> {
+        serde_cbor::to_vec(self)
+    }
+
+    /// Serializes this chain to CBOR like `to_cbor`, with every map sorted
+    /// by key. The maps of a chain are stored in no particular order, so two
+    /// equal chains may serialize to different bytes with `to_cbor`; with
+    /// this, they always serialize to the same bytes, which makes the output
+    /// suitable for caching by content or diffing. It is read back with
+    /// `from_cbor` as usual.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut a = Chain::new(1);
+    /// a.train(vec![1, 2, 3, 4, 5]);
+    /// let mut b = Chain::new(1);
+    /// b.train(vec![1, 2, 3, 4, 5]);
+    /// assert_eq!(a.to_cbor_sorted().unwrap(), b.to_cbor_sorted().unwrap());
+    /// ```
+    pub fn to_cbor_sorted(&self) -> serde_cbor::Result<Vec<u8>> where T: Ord {
+        serde_cbor::to_vec(&SortedChainData::new(self))
+    }
+
+    /// Deserializes a chain from CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> serde_cbor::Result<Self> {
+        serde_cbor::from_slice(bytes)
+    }
+}
+
+/// MessagePack serialization, enabled by the `rmp` feature.
+///
+/// Fields are written by name, so that the chain is easy to read from other
+/// languages.
+#[cfg(feature = "rmp")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to MessagePack.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    /// Deserializes a chain from MessagePack.
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+}
+
+/// YAML serialization, enabled by the `serde_yaml` feature.
+#[cfg(feature = "serde_yaml")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to YAML.
+    pub fn to_yaml(&self) -> serde_yaml::Result<String> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Deserializes a chain from YAML.
+    pub fn from_yaml(s: &str) -> serde_yaml::Result<Self> {
+        serde_yaml::from_str(s)
+    }
+}
+
+/// JSON serialization, enabled by the `serde_json` feature.
+///
+/// Since JSON objects can only have string keys, a chain is written with its
+/// nodes and links as lists of pairs rather than as objects.
+#[cfg(feature = "serde_json")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.list_data())
+    }
+
+    /// Serializes this chain to JSON, with indentation and line breaks.
+    pub fn to_json_pretty(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.list_data())
+    }
+
+    /// Deserializes a chain from JSON.
+    pub fn from_json(s: &str) -> serde_json::Result<Self> {
+        let data: ListChainData<T, W> = serde_json::from_str(s)?;
+        Chain::try_from(data.into_data()).map_err(serde::de::Error::custom)
+    }
+
+    /// Serializes this chain to JSON like `to_json`, with every node and link
+    /// sorted, so that equal chains always serialize to the same string. See
+    /// `to_cbor_sorted`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train(vec![2, 1]);
+    /// let json = chain.to_json_sorted().unwrap();
+    /// assert!(json.starts_with(r#"{"chain":[[[null],[[2,1]]],[[1],[[null,1]]],[[2],[[1,1]]]]"#));
+    /// ```
+    pub fn to_json_sorted(&self) -> serde_json::Result<String> where T: Ord {
+        let data = SortedChainData::new(self);
+        serde_json::to_string(&ListChainData::new(data.order, data.clock, data.chain, data.recency, data.break_tokens))
+    }
+
+    /// Lists the nodes, links and openings of this chain, in no particular
+    /// order.
+    fn list_data(&self) -> ListChainData<T, W> {
+        ListChainData::new(self.order, self.clock, &self.chain, &self.recency, self.custom_break_tokens())
+    }
+}
+
+/// An item, or the end of a string, in TOML. TOML has no null, so an item is
+/// written as a list of just that item, and `None` as an empty list.
+#[cfg(feature = "toml")]
+type TomlItem<T> = Vec<T>;
+
+/// A node and its links in TOML.
+#[cfg(feature = "toml")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, W: serde::Deserialize<'de>"))]
+struct TomlNode<T, W> {
+    node: Vec<TomlItem<T>>,
+    links: Vec<TomlLink<T, W>>,
+}
+
+/// A link in TOML.
+#[cfg(feature = "toml")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, W: serde::Deserialize<'de>"))]
+struct TomlLink<T, W> {
+    next: TomlItem<T>,
+    weight: W,
+}
+
+/// When a node was last trained, in TOML.
+#[cfg(feature = "toml")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>"))]
+struct TomlRecency<T> {
+    node: Vec<TomlItem<T>>,
+    time: u64,
+}
+
+/// The layout of a chain in TOML, which holds the same things as a
+/// `ChainData`. TOML tables can only have string keys, so the maps keyed by
+/// nodes and items are written as lists of records instead. The plain values
+/// come first, since TOML needs them before any tables.
+#[cfg(feature = "toml")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, W: serde::Deserialize<'de>"))]
+struct TomlChain<T, W> {
+    order: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    break_tokens: Option<BTreeSet<String>>,
+    #[serde(default)]
+    clock: u64,
+    #[serde(default)]
+    nodes: Vec<TomlNode<T, W>>,
+    #[serde(default)]
+    recency: Vec<TomlRecency<T>>,
+}
+
+#[cfg(feature = "toml")]
+impl<T, W> TomlChain<T, W> {
+    /// Gathers the records back into a chain's data, to be checked as it's
+    /// turned into a chain.
+    fn into_data(self) -> Result<ListChainData<T, W>, toml::de::Error> {
+        let mut chain = Vec::new();
+        for node in self.nodes {
+            let links = node.links.into_iter()
+                .map(|TomlLink { next, weight }| Ok((from_toml_item(next)?, weight)))
+                .collect::<Result<_, toml::de::Error>>()?;
+            chain.push((from_toml_node(node.node)?, links));
+        }
+        let recency = self.recency.into_iter()
+            .map(|TomlRecency { node, time }| Ok((from_toml_node(node)?, time)))
+            .collect::<Result<_, toml::de::Error>>()?;
+        Ok(ListChainData {
+            chain,
+            order: self.order,
+            recency,
+            clock: self.clock,
+            break_tokens: self.break_tokens,
+        })
+    }
+}
+
+#[cfg(feature = "toml")]
+fn to_toml_node<T: Clone>(node: &[Option<T>]) -> Vec<TomlItem<T>> {
+    node.iter()
+        .map(|item| item.iter().cloned().collect())
+        .collect()
+}
+
+#[cfg(feature = "toml")]
+fn from_toml_item<T>(mut item: TomlItem<T>) -> Result<Option<T>, toml::de::Error> {
+    if item.len() > 1 {
+        return Err(serde::de::Error::custom("an item must be a list of at most one value"));
+    }
+    Ok(item.pop())
+}
+
+#[cfg(feature = "toml")]
+fn from_toml_node<T>(node: Vec<TomlItem<T>>) -> Result<Node<T>, toml::de::Error> {
+    node.into_iter()
+        .map(from_toml_item)
+        .collect()
+}
+
+/// TOML serialization, enabled by the `toml` feature. This is meant for small
+/// chains that are edited by hand.
+///
+/// The settings of the chain are written first, followed by a `[[nodes]]`
+/// table for every node, holding the `node` itself and a `[[nodes.links]]`
+/// table for each of its links. A link has the item it goes to as `next`,
+/// and its `weight`. Since TOML has no null, every item is written as a list:
+/// `["cat"]` for an item, and `[]` for `None`, which is the padding at the
+/// start or end of a string in a node and the end of a string in a link.
+///
+/// ```toml
+/// order = 1
+/// break_tokens = [".", "?", "!"]
+///
+/// [[nodes]]
+/// node = [[]]
+///
+/// [[nodes.links]]
+/// next = ["cat"]
+/// weight = 2
+///
+/// [[nodes]]
+/// node = [["cat"]]
+///
+/// [[nodes.links]]
+/// next = []
+/// weight = 2
+/// ```
+///
+/// Nodes, links and openings are written in sorted order. Only `order` and
+/// `nodes` are needed to read a chain; the rest are given their defaults if
+/// they're left out. A node or link that is listed more than once has its
+/// weights added up.
+#[cfg(feature = "toml")]
+impl<T, W, H> Chain<T, W, H>
+    where T: Clone + Chainable + serde::Serialize + serde::de::DeserializeOwned,
+          W: Weight + serde::Serialize + serde::de::DeserializeOwned,
+          H: BuildHasher + Clone + Default {
+    /// Serializes this chain to TOML.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> where T: Ord {
+        let data = SortedChainData::new(self);
+        let toml = TomlChain {
+            order: data.order,
+            break_tokens: data.break_tokens.map(|tokens| tokens.into_iter().cloned().collect()),
+            clock: data.clock,
+            nodes: data.chain.into_iter()
+                .map(|(node, link)| TomlNode {
+                    node: to_toml_node(node),
+                    links: link.into_iter()
+                        .map(|(next, &weight)| TomlLink { next: next.iter().cloned().collect(), weight })
+                        .collect(),
+                })
+                .collect(),
+            recency: data.recency.into_iter()
+                .map(|(node, &time)| TomlRecency { node: to_toml_node(node), time })
+                .collect(),
+        };
+        toml::to_string(&toml)
+    }
+
+    /// Deserializes a chain from TOML.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let chain = Chain::<String>::from_toml(r#"
+    ///     order = 1
+    ///
+    ///     [[nodes]]
+    ///     node = [[]]
+    ///     links = [{ next = ["meow"], weight = 1 }]
+    ///
+    ///     [[nodes]]
+    ///     node = [["meow"]]
+    ///     links = [{ next = [], weight = 1 }]
+    /// "#).unwrap();
+    /// assert_eq!(chain.generate_sentence(), "meow");
+    /// ```
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        let toml: TomlChain<T, W> = toml::from_str(s)?;
+        Chain::try_from(toml.into_data()?.into_data()).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Conversions to and from the chains of the
+/// [markov](https://github.com/aatxe/markov) crate, enabled by the `aatxe`
+/// feature.
+///
+/// `markov` can only be trained by feeding it whole sequences, so a chain is
+/// converted by finding sequences that train into it and feeding those. This
+/// is lossless for chains trained on sequences at least as long as the order.
+/// Links that can't be part of such a sequence, as in a pruned chain, are
+/// left out.
+#[cfg(feature = "aatxe")]
+impl<T> Chain<T> where T: Clone + Chainable {
+    /// Converts this chain into a `markov::Chain`.
+    pub fn to_aatxe(&self) -> markov::Chain<T> {
+        let mut aatxe = markov::Chain::of_order(self.order);
+        for sequence in self.sequences() {
+            aatxe.feed(sequence);
+        }
+        aatxe
+    }
+
+    /// Finds sequences that train into this chain. Following a link to `None`
+    /// ends a sequence and goes back to the starting node, which makes every
+    /// trained link part of one circuit from the start. The circuit is found
+    /// by following links from the start until it comes back around, then
+    /// splicing in each loop that can be followed from a node along the way.
+    fn sequences(&self) -> Vec<Vec<T>> {
+        let start = vec![None; self.order];
+        let mut remaining = self.chain.clone();
+        // the links of the circuit as a linked list, so that loops can be
+        // spliced in anywhere: the node, the item that follows it, and the
+        // index of the next link
+        let mut circuit: Vec<(Node<T>, Option<T>, Option<usize>)> = Vec::new();
+        let mut head = None;
+        // the link being looked at, and the one before it, where loops go
+        let mut at: Option<usize> = None;
+        let mut before: Option<usize> = None;
+        loop {
+            let node = match at {
+                Some(index) => circuit[index].0.clone(),
+                None if head.is_none() => start.clone(),
+                None => break,
+            };
+            match take_loop(&mut remaining, &node, &start) {
+                Some(links) => {
+                    let first = circuit.len();
+                    let count = links.len();
+                    for (offset, (node, next)) in links.into_iter().enumerate() {
+                        let after = if offset + 1 == count { at } else { Some(first + offset + 1) };
+                        circuit.push((node, next, after));
+                    }
+                    match before {
+                        Some(before) => circuit[before].2 = Some(first),
+                        None => head = Some(first),
+                    }
+                    at = Some(first);
+                },
+                None if head.is_none() => break,
+                None => {
+                    before = at;
+                    at = circuit[at.unwrap()].2;
+                },
+            }
+        }
+
+        let mut sequences = Vec::new();
+        let mut sequence = Vec::new();
+        let mut index = head;
+        while let Some(i) = index {
+            match circuit[i].1 {
+                Some(ref item) => sequence.push(item.clone()),
+                None => sequences.push(mem::take(&mut sequence)),
+            }
+            index = circuit[i].2;
+        }
+        sequences
+    }
+}
+
+/// Conversions from the chains of the
+/// [markov](https://github.com/aatxe/markov) crate, enabled by the `aatxe`
+/// feature. `markov` only exposes the probability of each link, through its
+/// graph, so the links of the converted chain weigh their probabilities.
+/// These generate the same way the trained weights would.
+#[cfg(feature = "aatxe")]
+impl<T> Chain<T, f64> where T: Clone + Chainable {
+    /// Creates a chain from a `markov::Chain`.
+    pub fn from_aatxe(chain: &markov::Chain<T>) -> Self {
+        let graph = chain.graph();
+        // markov::Chain always has a starting node, so there's a node to
+        // find the order from
+        let order = graph.raw_nodes()[0].weight.len();
+        let mut result = Chain::with_weights(order);
+        for edge in graph.raw_edges() {
+            let next = &graph[edge.target()][order - 1];
+            result.update_link_weight(&graph[edge.source()], next, edge.weight);
+        }
+        result.rebuild_caches();
+        result
+    }
+}
+
+/// Gets the node that follows `node` when `next` is generated.
+fn advance<T: Clone>(node: &[Option<T>], next: &Option<T>) -> Node<T> {
+    let mut target = node[1 ..].to_vec();
+    target.push(next.clone());
+    target
+}
+
+/// Follows links from `from`, taking a weight of one from each, until it
+/// comes back around to `from`. A link to `None` goes back to `start`. Gives
+/// the node and item of each link followed, or `None` if it gets stuck
+/// first; the links followed before getting stuck are used up either way.
+#[cfg(feature = "aatxe")]
+fn take_loop<T>(remaining: &mut HashMap<Node<T>, Link<T>>, from: &[Option<T>], start: &[Option<T>])
+    -> Option<Vec<(Node<T>, Option<T>)>> where T: Clone + Chainable {
+    let mut curs = from.to_vec();
+    let mut links = Vec::new();
+    loop {
+        let next = {
+            let link = match remaining.get_mut(&curs) {
+                Some(link) if !link.is_empty() => link,
+                _ => return None,
+            };
+            let next = link.keys().next().unwrap().clone();
+            let weight = link[&next] - 1;
+            if weight == 0 {
+                link.remove(&next);
+            }
+            else {
+                link.insert(next.clone(), weight);
+            }
+            next
+        };
+        let target = match next {
+            Some(_) => advance(&curs, &next),
+            None => start.to_vec(),
+        };
+        links.push((mem::replace(&mut curs, target), next));
+        if curs == from {
+            return Some(links);
+        }
+    }
+}
+
+/// Calls `f` with each node of a string of items and the item that follows
+/// it, in the order they appear, like training the string would. This starts
+/// from the node of all `None` and ends with the link to `None`, and strings
+/// shorter than `order` are padded out with `None`. An empty string has no
+/// transitions at all, and neither does any string when the order is 0.
+fn for_each_transition<T, I, F>(order: usize, string: I, mut f: F)
+    where T: Clone,
+          I: IntoIterator<Item = T>,
+          F: FnMut(&[Option<T>], &Option<T>) {
+    let mut string = string.into_iter()
+        .map(Some)
+        .peekable();
+    // a chain of order 0 has no transitions to learn
+    if order == 0 || string.peek().is_none() {
+        return;
+    }
+
+    let mut window = vec!(None; order);
+    let mut count = 0;
+    loop {
+        let next = match string.next() {
+            Some(next) => next,
+            None if count < order => None,
+            None => break,
+        };
+        f(&window, &next);
+        window = advance(&window, &next);
+        count += 1;
+    }
+    f(&window, &None);
+}
+
+/// Chooses a random index into a list of weights, with the chance of each
+/// index being proportional to its weight.
+fn choose_weighted_index<S: RandomSource>(weights: &[f64], rng: &mut S) -> usize {
+    let total = weights.iter().sum::<f64>();
+    let mut choice = random_f64(rng) * total;
+    for (i, &weight) in weights.iter().enumerate() {
+        if choice < weight {
+            return i;
+        }
+        choice -= weight;
+    }
+    // only reachable through rounding error
+    weights.len() - 1
+}
+
+/// Chooses a random node out of `nodes`, preferring nodes without `None`
+/// padding. See `Chain::choose_random_node`.
+fn choose_start<'a, T, S>(mut nodes: Vec<&'a Node<T>>, rng: &mut S) -> Option<&'a Node<T>>
+    where T: Hash,
+          S: RandomSource {
+    fix_order(&mut nodes, rng, |&node| node);
+    let complete = nodes.iter()
+        .filter(|node| node.iter().all(Option::is_some))
+        .collect::<Vec<_>>();
+    if !complete.is_empty() {
+        Some(complete[random_index(rng, complete.len())])
+    }
+    else if !nodes.is_empty() {
+        Some(nodes[random_index(rng, nodes.len())])
+    }
+    else {
+        None
+    }
+}
+
+/// Sorts `items` by a stable hash of their keys when generating with a
+/// repeatable source, so that random choices between them don't depend on the
+/// order of a `HashMap`. See `RandomSource::is_repeatable`.
+fn fix_order<X, K, F, S>(items: &mut [X], rng: &S, key: F)
+    where K: Hash,
+          F: Fn(&X) -> K,
+          S: RandomSource {
+    if rng.is_repeatable() {
+        items.sort_by_cached_key(|item| stable_hash(&key(item)));
+    }
+}
+
+/// Hashes a value the same way in every run of a program, and in every build
+/// of it, unlike the hashers in the standard library, whose algorithm may
+/// change between Rust releases.
+fn stable_hash<H: Hash>(value: &H) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The 64-bit FNV-1a hash function, used by `stable_hash`.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A transition found by `Chain::rarest_links`, ordered by weight and then by
+/// a hash of the transition to break ties.
+struct RareLink<'a, T: 'a, W> {
+    weight: W,
+    tie: u64,
+    node: &'a Node<T>,
+    next: &'a Option<T>,
+}
+
+impl<'a, T, W: Weight> Ord for RareLink<'a, T, W> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.weight.partial_cmp(&other.weight)
+            .unwrap_or(cmp::Ordering::Equal)
+            .then(self.tie.cmp(&other.tie))
+    }
+}
+
+impl<'a, T, W: Weight> PartialOrd for RareLink<'a, T, W> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T, W: Weight> PartialEq for RareLink<'a, T, W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl<'a, T, W: Weight> Eq for RareLink<'a, T, W> {}
+
+/// Sums the weights of a link, saturating instead of overflowing.
+fn total_weight<T, W, H>(link: &Link<T, W, H>) -> W
+    where T: Chainable,
+          W: Weight,
+          H: BuildHasher {
+    link.values().fold(W::zero(), |total, &weight| total.saturating_add(weight))
+}
+
+/// Gets a random number in `[0, 1)`.
+fn random_f64<S: RandomSource>(rng: &mut S) -> f64 {
+    rng.next_u32() as f64 / (u32::MAX as f64 + 1.0)
+}
+
+/// Gets a random index into a list of `len` items.
+fn random_index<S: RandomSource>(rng: &mut S, len: usize) -> usize {
+    cmp::min((random_f64(rng) * len as f64) as usize, len - 1)
+}
+
+lazy_static! { 
+    /// Symbol combinations to break sentences on.
+    static ref BREAK: HashSet<String> = [".", "?", "!", ".\"", "!\"", "?\"", ",\"",
+                                         "。", "？", "！", "‽", ".”", "!”", "?”", ",”"].iter()
+        .map(|s| s.to_string())
+        .collect();
+    /// Punctuation that attaches to the word before it.
+    static ref CLOSING: [&'static str; 6] = [",", ";", ":", ")", "]", "}"];
+    /// Punctuation that the word after it attaches to.
+    static ref OPENING: [&'static str; 3] = ["(", "[", "{"];
+    /// Runs of the punctuation that `tokenize` splits out of words.
+    static ref PUNCTUATION_RUN: Regex = Regex::new(&format!(r#"^[{}"]+$"#, PUNCTUATION)).unwrap();
+}
+
+/// How many words a sentence read by `Chain::train_reader` can reach before it
+/// is ended at the end of a line.
+pub const MAX_READER_SENTENCE: usize = 10_000;
+
+/// The punctuation that `tokenize` splits out of words, as the inside of a
+/// regex character class: sentence terminators in any script, dashes, commas,
+/// ellipses and quotation marks. A `"` is split out in runs with these too,
+/// but doesn't end a word by itself.
+const PUNCTUATION: &str = r"\p{Sentence_Terminal}\p{Pd},，、…“”„«»";
+
+/// Quotation marks that always open a quote, so they attach to the word after
+/// them.
+const OPENING_QUOTES: &[char] = &['“', '„', '«'];
+
+/// Gets whether a word is a run of the punctuation that `tokenize` splits out.
+fn is_punctuation(word: &str) -> bool {
+    PUNCTUATION_RUN.is_match(word)
+}
+
+/// Gets whether a word is a run of en or em dashes, which attach to the words
+/// on both sides of them.
+fn is_joining_dash(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c == '–' || c == '—')
+}
+
+/// Gets whether a word is a run of hyphens standing in for a dash, which is
+/// spaced on both sides like a word.
+fn is_spaced_dash(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c == '-')
+}
+
+/// Gets the default set of words to break sentences on.
+fn default_break_tokens() -> HashSet<String> {
+    BREAK.clone()
+}
+
+/// Splits a string into words for `train_string`. Words are split by
+/// whitespace and punctuation, and any of the `breaks` that the punctuation
+/// rules wouldn't produce are split out as words of their own.
+fn tokenize(sentence: &str, breaks: &HashSet<String>) -> Vec<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            &format!(r#"[^\s{0}]+|[{0}"]+"#, PUNCTUATION)
+            ).unwrap();
+    };
+    // break tokens that the regex can't produce on its own are split out
+    // first, longest first
+    let mut splitting = breaks.iter()
+        .filter(|t| !t.is_empty() && !is_punctuation(t))
+        .map(|t| t.as_str())
+        .collect::<Vec<_>>();
+    splitting.sort_by_key(|t| cmp::Reverse(t.len()));
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < sentence.len() {
+        match splitting.iter().find(|t| sentence[i ..].starts_with(*t)) {
+            Some(token) => {
+                tokens.extend(RE.find_iter(&sentence[start .. i]).map(|m| String::from(m.as_str())));
+                tokens.push(String::from(*token));
+                i += token.len();
+                start = i;
+            },
+            None => i += sentence[i ..].chars().next().unwrap().len_utf8(),
+        }
+    }
+    tokens.extend(RE.find_iter(&sentence[start ..]).map(|m| String::from(m.as_str())));
+    tokens
+}
+
+/// Joins a list of words into a sentence.
+///
+/// Words are separated by single spaces, except that:
+/// * sentence breaks, closing punctuation (`,`, `;`, `:`, `)`, `]`, `}`) and
+///   any run of the punctuation that `train_string` splits out of words
+///   (sentence terminators like `.`, `!`, `?` and `。`, dashes, commas,
+///   ellipses and quotation marks) attach to the word before them, except
+///   for a run of hyphens standing on its own, like ` - ` or ` -- `, which
+///   keeps its spaces on both sides,
+/// * en and em dashes (`–`, `—`) attach to the words on both sides of them,
+/// * the word after an opening bracket (`(`, `[`, `{`) attaches to it, and
+/// * a `"` that opens a quote, and an opening quotation mark (`“`, `„`, `«`),
+///   attach to the following word instead of the preceding one.
+/// # Examples
+/// ```
+/// use markov_chain::detokenize;
+/// let words = ["He", "said", ",", "\"", "hi", "\"", "(", "twice", ")", "..."];
+/// let words = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+/// assert_eq!(detokenize(&words), "He said, \"hi\" (twice)...");
+/// ```
+pub fn detokenize(words: &[String]) -> String {
+    detokenize_with(words, &BREAK, |_, _| String::from(" "))
+}
+
+/// Joins a list of words into a sentence like `detokenize`, with `breaks` as
+/// the sentence breaks, asking `join` for the separator between two words that
+/// don't attach to each other.
+fn detokenize_with<F>(words: &[String], breaks: &HashSet<String>, join: F) -> String
+    where F: Fn(&str, &str) -> String {
+    let mut result = String::new();
+    let mut prev: Option<&str> = None;
+    let mut in_quote = false;
+    // whether the previous word attaches to the word after it
+    let mut opened = false;
+    for word in words {
+        let word = word.as_str();
+        let is_quote = word == "\"";
+        let opens_quote = (word.starts_with('"') && !in_quote) || word.starts_with(OPENING_QUOTES);
+        let closes = breaks.contains(word) || CLOSING.contains(&word)
+            || (is_punctuation(word) && !opens_quote && !is_spaced_dash(word));
+        if let Some(prev) = prev {
+            if !(closes || opened) {
+                result += &join(prev, word);
+            }
+        }
+        result += word;
+        opened = OPENING.contains(&word) || (opens_quote && is_punctuation(word)) || is_joining_dash(word);
+
+        // keep track of whether we're inside of a quotation
+        if is_quote {
+            in_quote = !in_quote;
+        }
+        else if word.ends_with('"') {
+            in_quote = false;
+        }
+        else if word.starts_with('"') {
+            in_quote = true;
+        }
+        prev = Some(word);
+    }
+    result
+}
+
+/// Uppercases the first letter of a sentence, skipping any punctuation or
+/// whitespace in front of it.
+fn capitalize(sentence: &str) -> String {
+    match sentence.char_indices().find(|&(_, c)| c.is_alphabetic()) {
+        Some((i, c)) => {
+            let rest = &sentence[i + c.len_utf8() ..];
+            format!("{}{}{}", &sentence[.. i], c.to_uppercase(), rest)
+        },
+        None => String::from(sentence),
+    }
+}
+
+/// String-specific implementation of the chain. Contains some special string-
+/// specific functions.
+impl<W, H> Chain<String, W, H> where W: Weight, H: BuildHasher + Clone + Default {
+    /// Gets the words that end a sentence. See `set_break_tokens`.
+    pub fn break_tokens(&self) -> &HashSet<String> {
+        &self.break_tokens
+    }
+
+    /// Sets the words that end a sentence, which are used to split sentences
+    /// in `train_string` and to end them in `generate_sentence`. The defaults
+    /// are `.`, `?`, `!`, `."`, `!"`, `?"` and `,"`, the same with `”` in place
+    /// of `"`, and `。`, `？`, `！` and `‽`.
+    ///
+    /// Break tokens made of anything besides the punctuation that
+    /// `train_string` splits out of words (see `detokenize`) are split out of
+    /// the text wherever they appear, even in the middle of a word, so that a
+    /// break like `;` or `\n` works for text that doesn't put spaces around
+    /// it.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.set_break_tokens(vec![String::from(";")])
+    ///     .train_string("the cat sat;");
+    /// assert_eq!(chain.generate_sentence(), "the cat sat;");
+    /// ```
+    pub fn set_break_tokens(&mut self, tokens: Vec<String>) -> &mut Self {
+        self.break_tokens = tokens.into_iter().collect();
+        self
+    }
+
+    /// Gets whether generated sentences are capitalized. See
+    /// `set_capitalize_sentences`.
+    pub fn capitalize_sentences(&self) -> bool {
+        self.capitalize_sentences
+    }
+
+    /// Sets whether generated sentences are capitalized, which uppercases the
+    /// first letter of every sentence from `generate_sentence` and the other
+    /// sentence and paragraph functions. This goes well with
+    /// `train_string_lowercased`. Sentences are not capitalized by default.
+    ///
+    /// This only affects generation, so it isn't saved when the chain is
+    /// serialized; set it again after loading a chain.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.set_capitalize_sentences(true)
+    ///     .train_string("\"the cat sat.\"");
+    /// assert_eq!(chain.generate_sentence(), "\"The cat sat.\"");
+    /// ```
+    pub fn set_capitalize_sentences(&mut self, capitalize: bool) -> &mut Self {
+        self.capitalize_sentences = capitalize;
+        self
+    }
+
+    /// Gets whether words are lowercased when training. See `set_lowercase`.
+    pub fn lowercase(&self) -> bool {
+        self.lowercase
+    }
+
+    /// Sets whether `train_string` and the other string training functions,
+    /// `train_string_with` included, lowercase every word before training,
+    /// like `train_string_lowercased`. Words are not lowercased by default.
+    ///
+    /// This isn't saved when the chain is serialized; set it again after
+    /// loading a chain to keep training it the same way.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.set_lowercase(true)
+    ///     .train_string("The cat sat.");
+    /// assert_eq!(chain.generate_sentence(), "the cat sat.");
+    /// ```
+    pub fn set_lowercase(&mut self, lowercase: bool) -> &mut Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Trains this chain on a single string. Strings are broken into words,
+    /// which are split by whitespace and punctuation. The words are
+    /// lowercased first if `set_lowercase` is on.
+    pub fn train_string(&mut self, sentence: &str) -> &mut Self {
+        let words = self.tokenize_string(sentence, self.lowercase);
+        self.train_words(words);
+        self
+    }
+
+    /// Trains this chain on the text of a file like `train_string`. The file
+    /// must be UTF-8; otherwise, an error of kind `InvalidData` is given and
+    /// nothing is trained.
+    /// # Examples
+    /// ```no_run
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_file("corpus.txt")?
+    ///     .train_file("more.txt")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn train_file<P>(&mut self, path: P) -> io::Result<&mut Self>
+        where P: AsRef<Path> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.train_string(&text))
+    }
+
+    /// Trains this chain on text read from `reader` like `train_string`,
+    /// one line at a time, so that only a line and the sentence it is part of
+    /// are held in memory at once. Sentences can span lines, and the chain
+    /// ends up the same as it would from training on the whole text at once.
+    /// This is the way to train on files too large to read into memory.
+    ///
+    /// Text without break tokens, like a list of words or source code, would
+    /// otherwise be one sentence as long as the whole text. To keep memory
+    /// bounded, a sentence that has reached `MAX_READER_SENTENCE` words is
+    /// ended at the end of the line it is on, as if it had a break there.
+    /// Only such long sentences are trained differently than by
+    /// `train_string`.
+    ///
+    /// The text must be UTF-8; otherwise, an error of kind `InvalidData` is
+    /// given. Sentences read before an error are still trained.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// use std::io::Cursor;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_reader(Cursor::new("the cat\nsat. the dog sat.\n"))?;
+    /// assert_eq!(chain.transition_weight(&[Some(String::from("cat"))], &Some(String::from("sat"))), 1);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn train_reader<R>(&mut self, reader: R) -> io::Result<&mut Self>
+        where R: io::BufRead {
+        self.train_reader_counted(reader)?;
+        Ok(self)
+    }
+
+    /// Trains this chain on text read from `reader` like `train_reader`,
+    /// returning the number of words it was broken into, like
+    /// `train_string_counted`.
+    pub fn train_reader_counted<R>(&mut self, mut reader: R) -> io::Result<usize>
+        where R: io::BufRead {
+        let mut count = 0;
+        let mut line = String::new();
+        let mut sentence = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            for word in self.tokenize_string(&line, self.lowercase) {
+                let breaks = self.break_tokens.contains(&word);
+                sentence.push(word);
+                if breaks {
+                    count += self.train_words(sentence.split_off(0));
+                }
+            }
+            if sentence.len() >= MAX_READER_SENTENCE {
+                count += self.train_words(sentence.split_off(0));
+            }
+        }
+        count += self.train_words(sentence);
+        Ok(count)
+    }
+
+    /// Creates a chain of the given order trained on the text of a file. See
+    /// `train_file`.
+    /// # Examples
+    /// ```no_run
+    /// use markov_chain::Chain;
+    /// let chain = Chain::<String>::from_text_file(2, "corpus.txt")?;
+    /// println!("{}", chain.generate_sentence());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_text_file<P>(order: usize, path: P) -> io::Result<Self>
+        where P: AsRef<Path> {
+        let mut chain = Chain::with_weights(order);
+        chain.train_file(path)?;
+        Ok(chain)
+    }
+
+    /// Trains this chain on a single string like `train_string`, lowercasing
+    /// every word first so that differently capitalized words are treated as
+    /// the same word. Break tokens are left as they are, so that they still
+    /// end sentences.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string_lowercased("The cat sat. THE END.");
+    /// assert_eq!(chain.transition_weight(&[None], &Some(String::from("the"))), 2);
+    /// ```
+    pub fn train_string_lowercased(&mut self, sentence: &str) -> &mut Self {
+        let words = self.tokenize_string(sentence, true);
+        self.train_words(words);
+        self
+    }
+
+    /// Trains this chain on a single string like `train_string`, using
+    /// `tokenizer` to break the string into words. The words are still split
+    /// into sentences on the break tokens, so the tokenizer should produce
+    /// sentence-ending punctuation as words of their own. The words are
+    /// lowercased afterwards if `set_lowercase` is on.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string_with("see https://example.com .", |s| {
+    ///     s.split_whitespace().map(String::from).collect()
+    /// });
+    /// assert_eq!(chain.generate_sentence(), "see https://example.com.");
+    /// ```
+    pub fn train_string_with<F>(&mut self, sentence: &str, tokenizer: F) -> &mut Self
+        where F: Fn(&str) -> Vec<String> {
+        let words = self.fold_case(tokenizer(sentence), self.lowercase);
+        self.train_words(words);
+        self
+    }
+
+    /// Trains this chain on a single string like `train_string`, returning
+    /// the number of words it was broken into, sentence breaks included. This
+    /// is handy for checking that some text was read as expected; use
+    /// `train_string` to chain calls instead.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// assert_eq!(chain.train_string_counted("the cat sat. the dog sat."), 8);
+    /// assert_eq!(chain.train_string_counted("   "), 0);
+    /// ```
+    pub fn train_string_counted(&mut self, sentence: &str) -> usize {
+        let words = self.tokenize_string(sentence, self.lowercase);
+        self.train_words(words)
+    }
+
+    /// Breaks a string into words with the default tokenizer, lowercasing
+    /// every word but the break tokens if `lowercase` is set.
+    fn tokenize_string(&self, sentence: &str, lowercase: bool) -> Vec<String> {
+        self.fold_case(tokenize(sentence, &self.break_tokens), lowercase)
+    }
+
+    /// Lowercases every word but the break tokens if `lowercase` is set.
+    fn fold_case(&self, words: Vec<String>, lowercase: bool) -> Vec<String> {
+        if !lowercase {
+            return words;
+        }
+        words.into_iter()
+            .map(|word| if self.break_tokens.contains(&word) { word } else { word.to_lowercase() })
+            .collect()
+    }
+
+    /// Trains this chain on a list of words, split into sentences on the break
+    /// tokens, returning the number of words.
+    fn train_words(&mut self, words: Vec<String>) -> usize {
+        let count = words.len();
+        let parts = {
+            let mut parts = Vec::new();
+            let mut part = Vec::new();
+            for word in words {
+                let breaks = self.break_tokens.contains(&word);
+                part.push(word);
+                if breaks {
+                    parts.push(part.clone());
+                    part.clear();
+                }
+            }
+            if !part.is_empty() {
+                parts.push(part);
+            }
+            parts
+        };
+        for string in parts {
+            self.train(string);
+        }
+        count
+    }
+
+    /// Generates a sentence, which are ended by "break" strings or null links.
+    /// "Break" strings are set with `set_break_tokens`, and default to:
+    /// `.`, `?`, `!`, `."`, `!"`, `?"`, `,"`, the same with `”` in place of
+    /// `"`, and `。`, `？`, `！`, `‽`
+    ///
+    /// The words of the sentence are joined with `detokenize`.
+    pub fn generate_sentence(&self) -> String {
+        self.generate_sentence_inner(|_, _| String::from(" "), None, &mut unseeded())
+    }
+
+    /// Generates a sentence like `generate_sentence`, using `rng` for every
+    /// random choice. Given the same chain and an RNG with the same seed, the
+    /// output is the same every time.
+    /// # Examples
+    /// ```
+    /// extern crate rand;
+    /// # extern crate markov_chain;
+    /// use markov_chain::Chain;
+    /// use rand::{SeedableRng, StdRng};
+    /// # fn main() {
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string("the cat sat. the dog sat on the cat.");
+    /// let first = chain.generate_sentence_with_rng(&mut StdRng::from_seed(&[42][..]));
+    /// let second = chain.generate_sentence_with_rng(&mut StdRng::from_seed(&[42][..]));
+    /// assert_eq!(first, second);
+    /// # }
+    /// ```
+    pub fn generate_sentence_with_rng<R>(&self, rng: &mut R) -> String
+        where R: Rng {
+        self.generate_sentence_inner(|_, _| String::from(" "), None, rng)
+    }
+
+    /// Generates a sentence like `generate_sentence`, using `join` to decide
+    /// what goes between two consecutive words. The joiner is given the
+    /// previous word and the next word, and returns the separator to place
+    /// between them.
+    ///
+    /// Punctuation is attached following the same rules as `detokenize`, so
+    /// the joiner is only consulted for word-to-word spacing.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string("私 は 猫 .");
+    /// // No spaces between words, as in CJK text
+    /// assert_eq!(chain.generate_sentence_with(|_, _| String::new()), "私は猫.");
+    /// ```
+    pub fn generate_sentence_with<F>(&self, join: F) -> String
+        where F: Fn(&str, &str) -> String {
+        self.generate_sentence_inner(join, None, &mut unseeded())
+    }
+
+    /// Generates a sentence like `generate_sentence`, where every word must
+    /// satisfy `allowed`. Sentence breaks are words too, so `allowed` should
+    /// accept them for sentences to end on punctuation. When no allowed word
+    /// can follow, the sentence ends there.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string("the cat sat. the dog sat.");
+    /// let sentence = chain.generate_sentence_filtered(|w| w != "dog");
+    /// assert!(!sentence.contains("dog"));
+    /// ```
+    pub fn generate_sentence_filtered<F>(&self, allowed: F) -> String
+        where F: Fn(&String) -> bool {
+        self.generate_sentence_inner(|_, _| String::from(" "), Some(&allowed), &mut unseeded())
+    }
+
+    /// Generates a sentence, joining words with `join` and only generating
+    /// words accepted by `allowed`, if given.
+    fn generate_sentence_inner<F, S>(&self, join: F, allowed: Option<&dyn Fn(&String) -> bool>,
+                                     rng: &mut S) -> String
+        where F: Fn(&str, &str) -> String,
+              S: RandomSource {
+        let mut walk = Walk::new(self, vec![None; self.order], allowed, Sampling::default());
+        let mut result = Vec::new();
+        while let Some(next) = walk.next(rng) {
+            let breaks = self.break_tokens.contains(&next);
+            result.push(next);
+            if breaks {
+                break;
+            }
+        }
+        self.render_sentence(&result, join)
+    }
+
+    /// Generates a sentence like `generate_sentence` with at least
+    /// `min_words` and at most `max_words` words. Punctuation isn't counted,
+    /// neither the break that ends the sentence nor commas and the like.
+    ///
+    /// Breaks are not chosen until `min_words` words have been generated.
+    /// When the sentence ends before that anyway, because nothing but a break
+    /// can follow, it is thrown away and started over. A chain might never
+    /// produce `min_words` words, so after 100 attempts this gives up and
+    /// returns the longest sentence it generated. Once `max_words` words have
+    /// been generated, the sentence is cut off there. Sentences that are cut
+    /// off or given up on still end with a break if one can follow the last
+    /// word. A `max_words` of 0 means there is no maximum.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string("yes. the cat sat on the mat and the dog sat on the cat.");
+    /// let sentence = chain.generate_sentence_bounded(3, 6);
+    /// assert!((3 ..= 6).contains(&sentence.split_whitespace().count()));
+    /// ```
+    pub fn generate_sentence_bounded(&self, min_words: usize, max_words: usize) -> String {
+        const MAX_ATTEMPTS: usize = 100;
+        let mut rng = unseeded();
+        let words = Cell::new(0);
+        let is_break = |word: &String| self.break_tokens.contains(word);
+        let allowed = |word: &String| words.get() >= min_words || !is_break(word);
+        let mut best = Vec::new();
+        let mut best_words = 0;
+        for _ in 0 .. MAX_ATTEMPTS {
+            words.set(0);
+            let mut walk = Walk::new(self, vec![None; self.order], Some(&allowed), Sampling::default());
+            let mut result = Vec::new();
+            let mut ended = false;
+            while let Some(next) = walk.next(&mut rng) {
+                ended = is_break(&next);
+                let is_word = !is_punctuation(&next);
+                result.push(next);
+                if ended {
+                    break;
+                }
+                if is_word {
+                    words.set(words.get() + 1);
+                }
+                if max_words > 0 && words.get() >= max_words {
+                    break;
+                }
+            }
+            // cut off at the ceiling or stuck below the floor, so end on a
+            // break if one can follow
+            if !ended && walk.termination != Some(Termination::Terminal) {
+                let end = self.choose_random_link_sampled(&walk.curs, Some(&is_break), Sampling::default(),
+                                                          &mut rng);
+                if let Some((Some(end), _)) = end {
+                    result.push(end.clone());
+                }
+            }
+            if words.get() >= min_words {
+                return self.render_sentence(&result, |_, _| String::from(" "));
+            }
+            if best.is_empty() || words.get() > best_words {
+                best = result;
+                best_words = words.get();
+            }
+        }
+        self.render_sentence(&best, |_, _| String::from(" "))
+    }
+
+    /// Joins generated words into a sentence with `join`, capitalizing it if
+    /// this chain is set to.
+    fn render_sentence<F>(&self, words: &[String], join: F) -> String
+        where F: Fn(&str, &str) -> String {
+        let sentence = detokenize_with(words, &self.break_tokens, join);
+        // a chain trained on whitespace can produce nothing but the joiner
+        if sentence.trim().is_empty() {
+            return String::new();
+        }
+        if self.capitalize_sentences {
+            return capitalize(&sentence);
+        }
+        sentence
+    }
+
+    /// Gets an endless iterator of sentences, generating one with
+    /// `generate_sentence` each time `next` is called. Unlike
+    /// `generate_paragraph`, nothing is generated until it's asked for, so
+    /// sentences can be written out as they come; `take` limits how many are
+    /// generated.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// chain.train_string("the cat sat. the dog sat on the cat.");
+    /// for sentence in chain.sentences().take(3) {
+    ///     assert!(sentence.ends_with('.'));
+    /// }
+    /// ```
+    pub fn sentences(&self) -> impl Iterator<Item = String> + '_ {
+        iter::repeat_with(move || self.generate_sentence())
+    }
+
+    /// Generates a paragraph of N sentences. Each sentence is broken off by N
+    /// spaces.
+    ///
+    /// Empty sentences are left out: each sentence gets a few attempts at
+    /// generating something, and is skipped if they all come up empty. The
+    /// paragraph may have fewer than N sentences on a chain that mostly
+    /// generates nothing, but never has doubled spaces.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(1);
+    /// // one in ten sentences is empty
+    /// chain.train(vec![String::new()]);
+    /// for _ in 0 .. 9 {
+    ///     chain.train_string("the end.");
+    /// }
+    /// assert_eq!(chain.generate_paragraph(3), "the end. the end. the end.");
+    /// ```
+    pub fn generate_paragraph(&self, sentences: usize) -> String {
+        self.generate_paragraph_inner(sentences, None, &mut unseeded())
+    }
+
+    /// Generates a paragraph like `generate_paragraph`, using `rng` for every
+    /// random choice. See `generate_sentence_with_rng`.
+    pub fn generate_paragraph_with_rng<R>(&self, rng: &mut R, sentences: usize) -> String
+        where R: Rng {
+        self.generate_paragraph_inner(sentences, None, rng)
+    }
+
+    /// Generates a paragraph like `generate_paragraph`, where every word must
+    /// satisfy `allowed`. See `generate_sentence_filtered`.
+    pub fn generate_paragraph_filtered<F>(&self, sentences: usize, allowed: F) -> String
+        where F: Fn(&String) -> bool {
+        self.generate_paragraph_inner(sentences, Some(&allowed), &mut unseeded())
+    }
+
+    /// Generates a paragraph like `generate_paragraph_filtered`, using `rng`
+    /// for every random choice. See `generate_sentence_with_rng`.
+    pub fn generate_paragraph_filtered_with_rng<R, F>(&self, rng: &mut R, sentences: usize, allowed: F) -> String
+        where R: Rng,
+              F: Fn(&String) -> bool {
+        self.generate_paragraph_inner(sentences, Some(&allowed), rng)
+    }
+
+    /// Generates a paragraph of up to `sentences` non-empty sentences, only
+    /// generating words accepted by `allowed`, if given.
+    fn generate_paragraph_inner<S>(&self, sentences: usize, allowed: Option<&dyn Fn(&String) -> bool>,
+                                   rng: &mut S) -> String
+        where S: RandomSource {
+        const ATTEMPTS_PER_SENTENCE: usize = 10;
+        let mut paragraph = Vec::new();
+        for _ in 0 .. sentences {
+            let sentence = (0 .. ATTEMPTS_PER_SENTENCE)
+                .map(|_| self.generate_sentence_inner(|_, _| String::from(" "), allowed, rng))
+                .find(|sentence| !sentence.is_empty());
+            if let Some(sentence) = sentence {
+                paragraph.push(sentence);
+            }
+        }
+        paragraph.join(" ")
+    }
+}
+
+/// Character-specific implementation of the chain, for modeling spelling
+/// rather than sentences. A `Chain<char>` trained on a list of words
+/// generates new words that look like them, which is handy for names and
+/// usernames.
+impl<W, H> Chain<char, W, H> where W: Weight, H: BuildHasher + Clone + Default {
+    /// Trains the chain on the characters of a string, as a single sequence.
+    /// To learn from a list of words, train each word separately.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// chain.train_chars("abc");
+    /// assert_eq!(chain.transition_weight(&[Some('a'), Some('b')], &Some('c')), 1);
+    /// ```
+    pub fn train_chars(&mut self, text: &str) -> &mut Self {
+        self.train_iter(text.chars())
+    }
+
+    /// Generates a string of characters like `generate`.
+    /// # Examples
+    /// ```
+    /// use markov_chain::Chain;
+    /// let mut chain = Chain::new(2);
+    /// for name in &["alice", "alina", "celia"] {
+    ///     chain.train_chars(name);
+    /// }
+    /// let name = chain.generate_string();
+    /// assert!(!name.is_empty());
+    /// ```
+    pub fn generate_string(&self) -> String {
+        self.generate().into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use std::{env, process};
+
+    macro_rules! test_get_link {
+        ($chain:expr, [$($key:expr),+]) => {{
+            let map = &$chain.chain;
+            let key = vec![$(Some($key),)+];
+            assert_eq!(key.len(), $chain.order);
+            assert!(map.contains_key(&key));
+            map.get(&key)
+                .unwrap()
+        }}; 
+    }
+
+    macro_rules! test_link_weight {
+        ($link:expr, $key:expr, $weight:expr) => {
+            let link = $link;
+            let key = $key;
+            assert!(link.contains_key(&key));
+            assert_eq!(*link.get(&key).unwrap(), $weight);
+        };
+    }
+
+    #[cfg(feature = "serde_cbor")]
+    #[test]
+    fn test_cbor_serialize() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let cbor_vec = chain.to_cbor();
+        assert!(cbor_vec.is_ok());
+        let de = Chain::from_cbor(&cbor_vec.unwrap()).unwrap();
+        assert_eq!(de, chain);
+        // the starting nodes aren't serialized, and are rebuilt instead
+        assert_eq!(de.starts.len(), chain.starts.len());
+    }
+
+    #[cfg(any(feature = "serde_cbor", feature = "serde_json"))]
+    #[test]
+    fn test_sorted_serialize() {
+        // each chain hashes differently, so their maps are in different orders
+        let train = || {
+            let mut chain = Chain::<u32>::new(2);
+            chain.train(vec![1, 2, 3, 1, 2, 4, 5, 6])
+                .train(vec![7, 8, 9, 1, 2, 3, 10]);
+            chain
+        };
+        let (a, b) = (train(), train());
+        #[cfg(feature = "serde_cbor")]
+        {
+            let bytes = a.to_cbor_sorted().unwrap();
+            assert_eq!(bytes, b.to_cbor_sorted().unwrap());
+            let de = Chain::<u32>::from_cbor(&bytes).unwrap();
+            assert_eq!(de, a);
+            assert_eq!(de.recency, a.recency);
+            assert_eq!(de.break_tokens, a.break_tokens);
+        }
+        #[cfg(feature = "serde_json")]
+        {
+            let json = a.to_json_sorted().unwrap();
+            assert_eq!(json, b.to_json_sorted().unwrap());
+            let de = Chain::<u32>::from_json(&json).unwrap();
+            assert_eq!(de, a);
+            assert_eq!(de.recency, a.recency);
+            assert_eq!(de.break_tokens, a.break_tokens);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_train_par() {
+        let sequences = (0 .. 200u32)
+            .map(|i| (i .. i + i % 7).map(|item| item % 13).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        for order in 1 .. 4 {
+            let mut sequential = Chain::new(order);
+            for sequence in &sequences {
+                sequential.train(sequence.clone());
+            }
+            let mut parallel = Chain::new(order);
+            parallel.train_par(sequences.clone());
+            assert_eq!(parallel, sequential);
+            assert_eq!(parallel.starts.len(), sequential.starts.len());
+
+            // training on top of an existing chain adds to it
+            let mut chain = Chain::new(order);
+            chain.train(vec![1, 2, 3])
+                .train_par(sequences.clone());
+            sequential.train(vec![1, 2, 3]);
+            assert_eq!(chain, sequential);
+        }
+    }
+
+    #[cfg(feature = "rmp")]
+    #[test]
+    fn test_msgpack_serialize() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let msgpack_vec = chain.to_msgpack();
+        assert!(msgpack_vec.is_ok());
+        let de = Chain::from_msgpack(&msgpack_vec.unwrap()).unwrap();
+        assert_eq!(de, chain);
+        // the starting nodes aren't serialized, and are rebuilt instead
+        assert_eq!(de.starts.len(), chain.starts.len());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_serialize() {
+        let mut chain = Chain::<String>::new(2);
+        chain.set_break_tokens(vec![String::from(";")])
+            .set_capitalize_sentences(true)
+            .train_string("one; two three; four");
+        let bytes = chain.to_bincode().unwrap();
+        let de = Chain::<String>::from_bincode(&bytes).unwrap();
+        assert_eq!(de, chain);
+        assert_eq!(de.recency, chain.recency);
+        assert_eq!(de.break_tokens(), chain.break_tokens());
+        assert!(!de.capitalize_sentences());
+        assert_eq!(de.starts.len(), chain.starts.len());
+        assert!(Chain::<String>::from_bincode(&bytes[.. bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_generate_sentence_empty() {
+        let mut chain = Chain::<String>::new(1);
+        assert_eq!(chain.generate_sentence(), "");
+        // the only link from the start is a break
+        chain.train_string(".");
+        assert_eq!(chain.generate_sentence(), ".");
+
+        let mut chain = Chain::new(1);
+        chain.train(vec![String::new(), String::from(" ")]);
+        assert_eq!(chain.generate_sentence(), "");
+    }
+
+    #[test]
+    fn test_generate_sentence_bounded() {
+        let mut chain = Chain::<String>::new(1);
+        assert_eq!(chain.generate_sentence_bounded(2, 4), "");
+        chain.train_string("yes. no. the cat sat on the mat and the dog sat on the cat.");
+        let word_count = |sentence: &str| sentence.split_whitespace().count();
+        for _ in 0 .. 50 {
+            // the floor: "yes." and "no." are never accepted
+            let sentence = chain.generate_sentence_bounded(3, 0);
+            assert!(word_count(&sentence) >= 3);
+            assert!(sentence.ends_with('.'));
+            // the ceiling: long sentences are ended early with a break, since
+            // every word here can be followed by one
+            let sentence = chain.generate_sentence_bounded(0, 2);
+            assert!((1 ..= 2).contains(&word_count(&sentence)));
+            let sentence = chain.generate_sentence_bounded(4, 4);
+            assert_eq!(word_count(&sentence), 4);
+        }
+
+        // no sentence is long enough, so this gives up with the longest one
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("yes. no. maybe so.");
+        for _ in 0 .. 10 {
+            assert_eq!(chain.generate_sentence_bounded(5, 10), "maybe so.");
+        }
+        // words without a break after them are cut off at the ceiling
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("one two three four five");
+        assert_eq!(chain.generate_sentence_bounded(1, 3), "one two three");
+        // punctuation isn't counted as words
+        let mut chain = Chain::<String>::new(2);
+        chain.train_string("one, two, three, four.");
+        assert_eq!(chain.generate_sentence_bounded(1, 3), "one, two, three");
+        assert_eq!(chain.generate_sentence_bounded(4, 0), "one, two, three, four.");
+    }
+
+    #[test]
+    fn test_sentences() {
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("I like cats. I like dogs.");
+        assert_eq!(chain.sentences().take(3).count(), 3);
+        for sentence in chain.sentences().take(10) {
+            assert!(sentence == "I like cats." || sentence == "I like dogs.");
+        }
+    }
+
+    #[test]
+    fn test_generate_paragraph_skips_empty() {
+        let mut chain = Chain::<String>::new(1);
+        assert_eq!(chain.generate_paragraph(3), "");
+        // every other sentence this generates is empty
+        chain.train(vec![String::new(), String::from(" ")]);
+        assert_eq!(chain.generate_paragraph(3), "");
+        chain.train_string("the end.");
+        for _ in 0 .. 50 {
+            let paragraph = chain.generate_paragraph(5);
+            assert!(!paragraph.contains("  "));
+            assert_eq!(paragraph.trim(), paragraph);
+            // ten empty attempts in a row are rare, but only ever shorten it
+            let count = paragraph.matches("the end.").count();
+            assert!((1 ..= 5).contains(&count));
+            assert_eq!(paragraph.split(' ').count(), count * 2);
+        }
+        let paragraph = chain.generate_paragraph_filtered(4, |w| w != "end");
+        assert!(!paragraph.contains("  "));
+        assert!(!paragraph.contains("end"));
+    }
+
+    #[test]
+    fn test_generate_with_rng_stable() {
+        use rand::{SeedableRng, StdRng};
+        // each map hashes differently, so these two chains store their nodes
+        // and links in different orders
+        let train = || {
+            let mut chain = Chain::<String>::new(1);
+            chain.train_string("the cat sat. the dog sat on the cat. a dog ran off! the end.");
+            chain
+        };
+        let (a, b) = (train(), train());
+        for seed in 0 .. 20 {
+            assert_eq!(a.generate_paragraph_with_rng(&mut StdRng::from_seed(&[seed][..]), 3),
+                       b.generate_paragraph_with_rng(&mut StdRng::from_seed(&[seed][..]), 3));
+            assert_eq!(a.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), -1),
+                       b.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), -1));
+        }
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        // the published FNV-1a test vectors, which must never change for a
+        // seed to keep generating the same output
+        let mut hasher = FnvHasher::default();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+        assert!(!unseeded().is_repeatable());
+        assert!(rand::thread_rng().is_repeatable());
+    }
+
+    #[test]
+    fn test_generate_sentence_with_rng() {
+        use rand::{SeedableRng, StdRng};
+        let mut chain = Chain::new(1);
+        chain.train_string("the cat sat. the dog sat on the cat. a dog ran off!");
+        for seed in 0 .. 20 {
+            let first = chain.generate_sentence_with_rng(&mut StdRng::from_seed(&[seed][..]));
+            let second = chain.generate_sentence_with_rng(&mut StdRng::from_seed(&[seed][..]));
+            assert_eq!(first, second);
+            let first = chain.generate_paragraph_with_rng(&mut StdRng::from_seed(&[seed][..]), 5);
+            let second = chain.generate_paragraph_with_rng(&mut StdRng::from_seed(&[seed][..]), 5);
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn test_break_tokens() {
+        let text = "one two. three four! five six";
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string(text);
+        assert_eq!(chain.break_tokens(), &*BREAK);
+        assert_eq!(test_get_link!(chain, [String::from(".")]), &hashmap!{None => 1});
+
+        // breaking on newlines and ideographic full stops instead
+        let mut chain = Chain::<String>::new(1);
+        chain.set_break_tokens(vec![String::from("\n"), String::from("。")])
+            .train_string("one two.\nthree four\n")
+            .train_string("私は猫。犬");
+        assert_eq!(test_get_link!(chain, [String::from(".")]), &hashmap!{Some(String::from("\n")) => 1});
+        assert_eq!(test_get_link!(chain, [String::from("\n")]), &hashmap!{None => 2});
+        assert_eq!(test_get_link!(chain, [String::from("私は猫")]), &hashmap!{Some(String::from("。")) => 1});
+        assert_eq!(chain.chain[&vec![None]].len(), 4);
+        for _ in 0 .. 20 {
+            let sentence = chain.generate_sentence();
+            assert!(sentence.ends_with('\n') || sentence.ends_with('。') || sentence == "犬");
+        }
+    }
+
+    #[test]
+    fn test_train_string_with() {
+        let tokenizer = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string_with("well-known @user ! don't stop .", tokenizer);
+        assert_eq!(test_get_link!(chain, [String::from("well-known")]),
+                   &hashmap!{Some(String::from("@user")) => 1});
+        // sentences are still split on break tokens
+        assert_eq!(test_get_link!(chain, [String::from("!")]), &hashmap!{None => 1});
+        assert_eq!(chain.chain[&vec![None]].len(), 2);
+
+        // train_string uses the default tokenizer
+        let mut chain1 = Chain::<String>::new(1);
+        let mut chain2 = Chain::<String>::new(1);
+        chain1.train_string("one, two-three. four");
+        chain2.train_string_with("one, two-three. four", |s| tokenize(s, &BREAK));
+        assert_eq!(chain1, chain2);
+
+        // counting the words trains the same as train_string
+        let mut chain3 = Chain::<String>::new(1);
+        assert_eq!(chain3.train_string_counted("one, two-three. four"), 7);
+        assert_eq!(chain3, chain1);
+    }
+
+    #[test]
+    fn test_zero_order() {
+        assert!(Chain::<u32>::try_new(0).is_none());
+        assert!(Chain::<u32>::try_new(1).is_some());
+
+        // a serialized chain can't have an order of 0 either
+        let data = ChainData::<u32, u32, RandomState> {
+            chain: HashMap::new(),
+            order: 0,
+            recency: HashMap::new(),
+            clock: 0,
+            break_tokens: None,
+        };
+        assert_eq!(Chain::try_from(data).unwrap_err(), ChainError::ZeroOrder);
+        #[cfg(feature = "serde_json")]
+        assert!(Chain::<u32>::from_json(r#"{"chain":[],"order":0}"#).is_err());
+        #[cfg(feature = "serde_yaml")]
+        assert!(Chain::<u32>::from_yaml("chain: {}\norder: 0").is_err());
+    }
+
+    #[test]
+    fn test_new_zero_order() {
+        // the infallible constructors don't panic, but make a chain that
+        // can't learn anything
+        let mut chain = Chain::<String>::new(0);
+        chain.train(vec![String::from("a"), String::from("b")])
+            .train_string("the cat sat.")
+            .forget(&[String::from("a")]);
+        assert!(chain.is_empty());
+        assert!(chain.generate().is_empty());
+        assert_eq!(chain.generate_from(&[String::from("a")], -1), vec![String::from("a")]);
+        assert_eq!(chain.generate_sentence(), "");
+        assert_eq!(Chain::<u32>::builder().order(0).build().order(), 0);
+        assert!(Chain::<u32, f64>::with_weights(0).is_empty());
+    }
+
+    #[test]
+    fn test_default() {
+        let chain = Chain::<u32>::default();
+        assert_eq!(chain.order(), 1);
+        assert!(chain.is_empty());
+        assert_eq!(chain, Chain::new(1));
+
+        #[derive(Default)]
+        struct Model {
+            chain: Chain<String, f64>,
+        }
+        let model = Model::default();
+        assert_eq!(model.chain.order(), 1);
+        assert!(model.chain.is_empty());
+    }
+
+    #[test]
+    fn test_into_chain_from_raw() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 1, 2, 4]);
+        let copy = chain.clone();
+        let raw = chain.into_chain();
+        assert_eq!(raw.len(), copy.node_count());
+        let chain = Chain::from_raw(raw, 2).unwrap();
+        assert_eq!(chain, copy);
+        // starting nodes are rebuilt, so generation works right away
+        assert!(!chain.generate().is_empty());
+
+        // a node of the wrong length is rejected
+        let mut raw = chain.into_chain();
+        raw.insert(vec![Some(1)], hashmap!{ Some(2) => 1 });
+        assert_eq!(Chain::from_raw(raw.clone(), 2).unwrap_err(), ChainError::NodeLength);
+        assert_eq!(Chain::from_raw(raw, 1).unwrap_err(), ChainError::NodeLength);
+        assert_eq!(Chain::<u32>::from_raw(HashMap::new(), 0).unwrap_err(), ChainError::ZeroOrder);
+        assert_eq!(ChainError::ZeroOrder.to_string(), ZERO_ORDER);
+        #[cfg(feature = "serde_json")]
+        assert!(Chain::<u32>::from_json(r#"{"chain":[[[1,2],[[3,1]]]],"order":1}"#).is_err());
+    }
+
+    #[test]
+    fn test_builder() {
+        let chain = Chain::<String>::builder().build();
+        assert_eq!(chain.order(), 1);
+        assert_eq!(chain.break_tokens(), &*BREAK);
+        assert!(!chain.lowercase());
+        assert!(!chain.capitalize_sentences());
+
+        let mut builder = ChainBuilder::<String, f64>::new();
+        builder.order(3)
+            .break_tokens(vec![String::from("!")])
+            .lowercase(true);
+        let mut chain = builder.build();
+        assert_eq!(chain.order(), 3);
+        assert!(chain.lowercase());
+        chain.train_string("Hi THERE! Bye.");
+        assert!(chain.chain.contains_key(&vec![Some(String::from("hi")), Some(String::from("there")),
+                                               Some(String::from("!"))]));
+        assert_eq!(chain.train_string_counted("BYE"), 1);
+        assert_eq!(chain.transition_weight(&[None, None, Some(String::from("bye"))], &Some(String::from("."))),
+                   1.0);
+        // the builder can be used again
+        assert_eq!(builder.build().order(), 3);
+    }
+
+    #[test]
+    fn test_train_file() {
+        let path = env::temp_dir().join(format!("markov-chain-{}-train-file.txt", process::id()));
+        fs::write(&path, "the cat sat. the dog sat.").unwrap();
+        let mut chain = Chain::<String>::new(1);
+        chain.train_file(&path).unwrap();
+        let mut expected = Chain::<String>::new(1);
+        expected.train_string("the cat sat. the dog sat.");
+        assert_eq!(chain, expected);
+        assert_eq!(Chain::<String>::from_text_file(1, &path).unwrap(), expected);
+
+        fs::write(&path, b"\xff\xfe").unwrap();
+        assert_eq!(chain.train_file(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(chain.train_file(&path).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(chain, expected);
+    }
+
+    #[test]
+    fn test_train_reader() {
+        let text = "the cat\nsat. the dog\n\nsat on\r\nthe cat! and\nthat was that";
+        let mut chain = Chain::<String>::new(2);
+        assert_eq!(chain.train_reader_counted(io::Cursor::new(text)).unwrap(), 15);
+        let mut expected = Chain::<String>::new(2);
+        assert_eq!(expected.train_string_counted(text), 15);
+        assert_eq!(chain, expected);
+        assert!(chain.train_reader(io::Cursor::new("")).is_ok());
+        assert_eq!(chain, expected);
+
+        // lowercasing applies to each line
+        let mut chain = Chain::<String>::new(1);
+        chain.set_lowercase(true)
+            .train_reader(io::Cursor::new("The CAT\nsat."))
+            .unwrap();
+        assert_eq!(chain.generate_sentence(), "the cat sat.");
+
+        // and to words from a custom tokenizer
+        let mut chain = Chain::<String>::new(1);
+        chain.set_lowercase(true)
+            .train_string_with("The CAT sat .", |s| s.split(' ').map(String::from).collect());
+        assert_eq!(chain.generate_sentence(), "the cat sat.");
+
+        // sentences before invalid text are kept
+        let mut chain = Chain::<String>::new(1);
+        let err = chain.train_reader(io::Cursor::new(&b"the cat sat.\n\xff\n"[..])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(chain.generate_sentence(), "the cat sat.");
+
+        // text without breaks is split into sentences at line ends
+        let text = "a b\n".repeat(MAX_READER_SENTENCE);
+        let mut chain = Chain::<String>::new(1);
+        assert_eq!(chain.train_reader_counted(io::Cursor::new(text)).unwrap(), 2 * MAX_READER_SENTENCE);
+        assert_eq!(chain.transition_weight(&[None], &Some(String::from("a"))), 2);
+        assert_eq!(chain.transition_weight(&[Some(String::from("b"))], &None), 2);
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string_lowercased("The cat sat. the CAT ran. THE END.");
+        assert_eq!(test_get_link!(chain, [String::from("the")]),
+                   &hashmap!{Some(String::from("cat")) => 2, Some(String::from("end")) => 1});
+        assert!(!chain.chain.contains_key(&vec![Some(String::from("The"))]));
+        assert!(!chain.chain.contains_key(&vec![Some(String::from("CAT"))]));
+
+        // custom break tokens aren't lowercased, so they still end sentences
+        let mut chain = Chain::<String>::new(1);
+        chain.set_break_tokens(vec![String::from("STOP")])
+            .train_string_lowercased("Go STOP");
+        assert_eq!(test_get_link!(chain, [String::from("STOP")]), &hashmap!{None => 1});
+
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string_lowercased("\"Über alles\" ok.");
+        assert!(!chain.capitalize_sentences());
+        assert_eq!(chain.generate_sentence(), "\"über alles\" ok.");
+        chain.set_capitalize_sentences(true);
+        assert_eq!(chain.generate_sentence(), "\"Über alles\" ok.");
+        assert_eq!(chain.generate_paragraph(2), "\"Über alles\" ok. \"Über alles\" ok.");
+        assert!(chain.retrain_order(1).unwrap().capitalize_sentences());
+        assert_eq!(capitalize("... 123"), "... 123");
+    }
+
+    #[cfg(feature = "serde_cbor")]
+    #[test]
+    fn test_cbor_break_tokens() {
+        let mut chain = Chain::<String>::new(1);
+        chain.set_break_tokens(vec![String::from(";")])
+            .train_string("one; two");
+        chain.set_capitalize_sentences(true)
+            .set_lowercase(true);
+        let de = Chain::<String>::from_cbor(&chain.to_cbor().unwrap()).unwrap();
+        assert_eq!(de.break_tokens(), chain.break_tokens());
+        // settings for training and generation aren't serialized
+        assert!(!de.capitalize_sentences());
+        assert!(!de.lowercase());
+    }
+
+    #[test]
+    fn test_default_break_tokens_left_out() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2]);
+        #[cfg(feature = "serde_yaml")]
+        {
+            let yaml = chain.to_yaml().unwrap();
+            assert!(!yaml.contains('!'), "{}", yaml);
+            assert_eq!(Chain::<u32>::from_yaml(&yaml).unwrap().break_tokens, *BREAK);
+        }
+        #[cfg(feature = "serde_json")]
+        {
+            let json = chain.to_json().unwrap();
+            assert!(!json.contains("break_tokens"), "{}", json);
+            assert_eq!(Chain::<u32>::from_json(&json).unwrap().break_tokens, *BREAK);
+        }
+        #[cfg(feature = "toml")]
+        {
+            let toml = chain.to_toml().unwrap();
+            assert!(!toml.contains("break_tokens"), "{}", toml);
+            assert_eq!(Chain::<u32>::from_toml(&toml).unwrap().break_tokens, *BREAK);
+        }
+        // formats that can't leave fields out still read them back
+        #[cfg(feature = "bincode")]
+        {
+            let bytes = chain.to_bincode().unwrap();
+            assert_eq!(Chain::<u32>::from_bincode(&bytes).unwrap().break_tokens, *BREAK);
+            chain.break_tokens = hashset!{String::from(";")};
+            let bytes = chain.to_bincode().unwrap();
+            assert_eq!(Chain::<u32>::from_bincode(&bytes).unwrap().break_tokens, chain.break_tokens);
+        }
+    }
+
+    #[cfg(feature = "aatxe")]
+    #[test]
+    fn test_aatxe_conversion() {
+        let mut chain = Chain::<String>::new(2);
+        chain.train_string("the cat sat on the mat. the dog sat on the cat.");
+        let aatxe = chain.to_aatxe();
+        assert!(!aatxe.is_empty());
+        let generated = aatxe.generate();
+        assert!(generated.len() >= 2);
+        // the links come back weighing their probabilities
+        let converted = Chain::from_aatxe(&aatxe);
+        assert_eq!(converted.node_count(), chain.node_count());
+        for (node, link) in chain.chain() {
+            let total = link.values().sum::<u32>() as f64;
+            let converted = &converted.chain()[node];
+            assert_eq!(converted.len(), link.len());
+            for (next, &weight) in link {
+                assert!((converted[next] - weight as f64 / total).abs() < 1e-9);
+            }
+        }
+
+        let mut aatxe = markov::Chain::of_order(2);
+        aatxe.feed(vec![1u32, 2, 3]);
+        let mut expected = Chain::<u32, f64>::with_weights(2);
+        expected.train(vec![1, 2, 3]);
+        assert_eq!(Chain::from_aatxe(&aatxe), expected);
+        // sequences trained into a chain are fed to markov the same way
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 1, 2, 4]);
+        let mut expected = markov::Chain::of_order(2);
+        expected.feed(vec![1, 2, 3])
+            .feed(vec![1, 2, 1, 2, 4]);
+        assert_eq!(chain.to_aatxe(), expected);
+        // a pruned link can't be walked to, so it's left out
+        chain.chain.remove(&vec![None, Some(1)]);
+        chain.rebuild_caches();
+        assert!(chain.to_aatxe().is_empty());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_json_serialize() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let json_str = chain.to_json();
+        assert!(json_str.is_ok());
+        let de = Chain::from_json(&json_str.unwrap());
+        assert_eq!(de.unwrap(), chain);
+        let de = Chain::from_json(&chain.to_json_pretty().unwrap());
+        assert_eq!(de.unwrap(), chain);
+        // a node listed twice has its weights added up
+        let de = Chain::<u32>::from_json(r#"{"chain":[[[1],[[2,1]]],[[1],[[2,2]]]],"order":1}"#).unwrap();
+        assert_eq!(de.transition_weight(&[Some(1)], &Some(2)), 3);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_serialize() {
+        let mut chain = Chain::<String>::new(2);
+        chain.train_string("the cat sat. the dog sat on the cat.")
+            .set_capitalize_sentences(true)
+            .train(vec![String::from("hi")]);
+        let toml_str = chain.to_toml().unwrap();
+        let de = Chain::<String>::from_toml(&toml_str).unwrap();
+        assert_eq!(de, chain);
+        assert_eq!(de.recency, chain.recency);
+        assert!(!de.capitalize_sentences());
+        assert!(!toml_str.contains("capitalize_sentences"));
+        assert_eq!(de.break_tokens(), chain.break_tokens());
+
+        let mut chain = Chain::<u32, f64>::with_weights(1);
+        chain.train(vec![1, 2, 3]).decay(0.5);
+        assert_eq!(Chain::<u32, f64>::from_toml(&chain.to_toml().unwrap()).unwrap(), chain);
+
+        // bad orders, nodes and items are errors
+        assert!(Chain::<u32>::from_toml("order = 0").is_err());
+        assert!(Chain::<u32>::from_toml("order = 1\n[[nodes]]\nnode = [[1], [2]]\nlinks = []").is_err());
+        assert!(Chain::<u32>::from_toml("order = 1\n[[nodes]]\nnode = [[1, 2]]\nlinks = []").is_err());
+        assert!(Chain::<u32>::from_toml("order = 1\n[[nodes]]\nnode = [[1]]\nlinks = [{ next = [1, 2], weight = 1 }]")
+            .is_err());
+        let empty = Chain::<u32>::from_toml("order = 2").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(&empty.break_tokens, &*BREAK);
+        assert!(Chain::<u32>::from_toml("order = 1\n[[recency]]\nnode = [[1], [2]]\ntime = 1").is_err());
+
+        // nodes and links listed more than once are added up
+        let chain = Chain::<u32>::from_toml("order = 1\n\
+            [[nodes]]\nnode = [[1]]\nlinks = [{ next = [2], weight = 1 }, { next = [2], weight = 2 }]\n\
+            [[nodes]]\nnode = [[1]]\nlinks = [{ next = [], weight = 4 }]").unwrap();
+        assert_eq!(chain.transition_weight(&[Some(1)], &Some(2)), 3);
+        assert_eq!(chain.transition_weight(&[Some(1)], &None), 4);
+
+        // nodes are written in order, so equal chains are written the same way
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![2, 1]);
+        let toml_str = chain.to_toml().unwrap();
+        let positions = ["node = [[]]", "node = [[1]]", "node = [[2]]"].iter()
+            .map(|node| toml_str.find(node).unwrap())
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn test_yaml_serialize() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let yaml_str = chain.to_yaml();
+        assert!(yaml_str.is_ok());
+        let de = Chain::from_yaml(&yaml_str.unwrap());
+        assert_eq!(de.unwrap(), chain);
+    }
+
+    #[test]
+    fn test_nodes() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 4])
+            .train(vec![5])
+            .train(vec![2, 3, 1]);
+        assert_eq!(chain.nodes().count(), chain.node_count());
+        assert!(chain.nodes().all(|node| chain.links(node).is_some()));
+        let starting = chain.starting_nodes().cloned().collect::<HashSet<_>>();
+        assert!(starting.iter().all(|node| node.contains(&None)));
+        assert_eq!(starting, hashset!{
+            vec![None, None],
+            vec![None, Some(1)],
+            vec![None, Some(5)],
+            vec![None, Some(2)],
+        });
+        assert_eq!(Chain::<u32>::new(2).nodes().count(), 0);
+    }
+
+    #[test]
+    fn test_order1_training() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        assert_eq!(chain.vocabulary(), hashset!{&1, &2, &3, &4});
+        assert_eq!(chain.vocabulary_size(), 4);
+        let link = test_get_link!(chain, [1u32]);
+        test_link_weight!(link, Some(2u32), 1);
+        test_link_weight!(link, Some(3u32), 1);
+
+        let link = test_get_link!(chain, [2u32]);
+        test_link_weight!(link, Some(3u32), 2);
+
+        let link = test_get_link!(chain, [3u32]);
+        test_link_weight!(link, None, 1);
+        test_link_weight!(link, Some(4u32), 2);
+
+        let link = test_get_link!(chain, [4u32]);
+        test_link_weight!(link, None, 2);
+
+        assert_eq!(chain.node_count(), 5);
+        assert_eq!(chain.edge_count(), 8);
+        assert_eq!(chain.total_observations(), 12);
+    }
+
+    #[test]
+    fn test_contains_node_get_weight() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        for node in 1 .. 5 {
+            assert!(chain.contains_node(&[Some(node)]));
+        }
+        assert!(chain.contains_node(&[None]));
+        assert!(!chain.contains_node(&[Some(5)]));
+
+        assert_eq!(chain.get_weight(&[Some(1)], &Some(2)), Some(1));
+        assert_eq!(chain.get_weight(&[Some(2)], &Some(3)), Some(2));
+        assert_eq!(chain.get_weight(&[Some(3)], &None), Some(1));
+        assert_eq!(chain.get_weight(&[Some(4)], &None), Some(2));
+        assert_eq!(chain.get_weight(&[Some(2)], &None), None);
+        assert_eq!(chain.get_weight(&[Some(5)], &Some(1)), None);
+    }
+
+    #[test]
+    fn test_train_slice_iter() {
+        let strings = [vec![1u32, 2, 3, 4, 2, 3], vec![2, 3, 4], vec![1], vec![]];
+        for order in 1 .. 4 {
+            let mut trained = Chain::new(order);
+            let mut sliced = Chain::new(order);
+            let mut iterated = Chain::new(order);
+            for string in &strings {
+                trained.train(string.clone());
+                sliced.train_slice(string);
+                iterated.train_iter(string.iter().cloned());
+            }
+            assert_eq!(trained, sliced);
+            assert_eq!(trained, iterated);
+            assert_eq!(trained.recency, sliced.recency);
+            assert_eq!(trained.recency, iterated.recency);
+        }
+    }
+
+    #[test]
+    fn test_clear_forget() {
+        for order in 1 .. 4 {
+            let mut chain = Chain::<u32>::new(order);
+            chain.train(vec![1, 2, 3, 1, 2]);
+            chain.forget(&[1, 2, 3, 1, 2]);
+            assert!(chain.is_empty());
+            assert!(chain.recency.is_empty());
+            assert!(chain.starts.is_empty());
+
+            let mut chain = Chain::<u32>::new(order);
+            chain.train(vec![2, 3, 4]);
+            let before = chain.clone();
+            chain.train(vec![1, 2, 3, 1, 2])
+                .forget(&[1, 2, 3, 1, 2]);
+            assert_eq!(chain, before);
+            assert_eq!(chain.starts.len(), before.starts.len());
+            // PartialEq doesn't compare recency
+            assert_eq!(chain.recency, before.recency);
+            assert_eq!(chain.clock, before.clock);
+            // forgetting something that was never trained changes nothing
+            chain.forget(&[7, 8, 9]);
+            assert_eq!(chain, before);
+            assert_eq!(chain.recency, before.recency);
+            assert_eq!(chain.clock, before.clock);
+
+            // a sequence that opens like another keeps the opening around
+            let mut chain = Chain::<u32>::new(order);
+            chain.train(vec![1, 2, 3, 4]);
+            let before = chain.clone();
+            chain.train(vec![1, 2, 3, 5])
+                .forget(&[1, 2, 3, 5]);
+            assert_eq!(chain, before);
+            assert_eq!(chain.recency, before.recency);
+            assert_eq!(chain.clock, before.clock);
+            // forgetting an older sequence leaves the clock alone
+            chain.train(vec![6, 7, 8])
+                .forget(&[1, 2, 3, 4]);
+            assert_eq!(chain.clock, 2);
+            assert_eq!(chain.recency.len(), 1);
+            assert_eq!(chain.recency.values().next(), Some(&2));
+
+            chain.clear();
+            assert!(chain.is_empty());
+            assert_eq!(chain.order(), order);
+            assert!(chain.generate().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_interpolated_probability() {
+        let mut high = Chain::<u32>::new(2);
+        let mut low = Chain::<u32>::new(1);
+        for chain in &mut [&mut high, &mut low] {
+            chain.train(vec![1, 2, 3]);
+            chain.train(vec![1, 2, 3]);
+            chain.train(vec![4, 2, 5]);
+        }
+        let node = [Some(1), Some(2)];
+        // p_high = 1, p_low = 2 / 3
+        assert_eq!(high.interpolated_probability(&low, &node, &Some(3), 1.0), 1.0);
+        assert_eq!(high.interpolated_probability(&low, &node, &Some(3), 0.0), 2.0 / 3.0);
+        let p = high.interpolated_probability(&low, &node, &Some(3), 0.25);
+        assert!((p - (0.25 + 0.75 * 2.0 / 3.0)).abs() < 1e-12);
+        // p_high = 0, p_low = 1 / 3
+        let p = high.interpolated_probability(&low, &node, &Some(5), 0.4);
+        assert!((p - 0.6 / 3.0).abs() < 1e-12);
+        assert_eq!(high.interpolated_probability(&low, &node, &Some(9), 0.5), 0.0);
+        // a chain can be interpolated with itself
+        assert_eq!(low.interpolated_probability(&low, &[Some(2)], &Some(3), 0.3), 2.0 / 3.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_interpolated_probability_order() {
+        let low = Chain::<u32>::new(1);
+        let high = Chain::<u32>::new(2);
+        low.interpolated_probability(&high, &[Some(1)], &Some(2), 0.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_interpolated_probability_lambda() {
+        let high = Chain::<u32>::new(2);
+        let low = Chain::<u32>::new(1);
+        high.interpolated_probability(&low, &[Some(1), Some(2)], &Some(3), 1.5);
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let mut chains = vec![Chain::<u32>::new(1), Chain::new(1), Chain::new(1)];
+        chains[0].train(vec![1, 2, 3]);
+        chains[1].train(vec![1, 2, 4, 5]);
+        chains[2].train(vec![3, 2, 1]).train(vec![1, 2, 3]);
+        let mut sequential = chains[0].clone();
+        sequential.merge(&chains[1]).merge(&chains[2]);
+        let merged = Chain::merge_all(&chains).unwrap();
+        assert_eq!(merged, sequential);
+        assert_eq!(merged.transition_weight(&[Some(1)], &Some(2)), 3);
+        // the inputs are left alone
+        assert_eq!(chains[0].transition_weight(&[Some(1)], &Some(2)), 1);
+        assert_eq!(Chain::merge_all(&chains[.. 1]).unwrap(), chains[0]);
+        assert!(Chain::<u32>::merge_all(&[]).is_none());
+        chains.push(Chain::new(2));
+        assert_eq!(Chain::try_merge_all(&chains).unwrap_err(),
+                   MergeError::OrderMismatch { self_order: 1, other_order: 2 });
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_merge_all_order() {
+        Chain::<u32>::merge_all(&[Chain::new(1), Chain::new(2)]);
+    }
+
+    #[test]
+    fn test_observed_count() {
+        let mut chain = Chain::<String>::new(2);
+        chain.train_string("the quick brown fox jumps.")
+            .train_string("the quick brown fox jumps.")
+            .train_string("a quick brown dog sleeps.");
+        let phrase = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(chain.observed_count(&phrase("the quick brown fox")), 2);
+        assert_eq!(chain.observed_count(&phrase("quick brown")), 0);
+        assert_eq!(chain.observed_count(&phrase("quick brown fox")), 2);
+        assert_eq!(chain.observed_count(&phrase("a quick brown dog sleeps")), 1);
+        assert_eq!(chain.observed_count(&phrase("a quick brown fox")), 1);
+        // every step was trained, though never all in one sentence
+        assert_eq!(chain.observed_count(&phrase("the quick brown dog")), 1);
+        assert_eq!(chain.observed_count(&phrase("the quick red fox")), 0);
+    }
+
+    #[test]
+    fn test_dead_ends() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 4, 5])
+            .train(vec![2, 3, 6]);
+        assert!(chain.is_dead_end(&[Some(4), Some(5)]));
+        assert!(chain.is_dead_end(&[Some(3), Some(6)]));
+        // [2, 3] ends one sequence but continues another
+        assert!(!chain.is_dead_end(&[Some(2), Some(3)]));
+        assert!(!chain.is_dead_end(&[Some(1), Some(2)]));
+        assert!(!chain.is_dead_end(&[Some(5), Some(6)]));
+        let mut dead_ends = chain.dead_ends();
+        dead_ends.sort();
+        assert_eq!(dead_ends, vec![&vec![Some(3), Some(6)], &vec![Some(4), Some(5)]]);
+        assert!(Chain::<u32>::new(1).dead_ends().is_empty());
+    }
+
+    #[test]
+    fn test_links_sorted() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.update_link_weight(&[Some(1)], &Some(2), 1);
+        chain.update_link_weight(&[Some(1)], &Some(3), 5);
+        chain.update_link_weight(&[Some(1)], &Some(4), 3);
+        assert_eq!(chain.links_sorted(&[Some(1)]), vec![(&Some(3), 5), (&Some(4), 3), (&Some(2), 1)]);
+        // ties are broken by the item, with the end first
+        chain.update_link_weight(&[Some(1)], &Some(5), 3);
+        chain.update_link_weight(&[Some(1)], &None, 3);
+        assert_eq!(chain.links_sorted(&[Some(1)]),
+                   vec![(&Some(3), 5), (&None, 3), (&Some(4), 3), (&Some(5), 3), (&Some(2), 1)]);
+        assert!(chain.links_sorted(&[Some(9)]).is_empty());
+        assert!(chain.links_sorted(&[Some(1), Some(2)]).is_empty());
+    }
+
+    #[test]
+    fn test_most_common_starts() {
+        let mut chain = Chain::<String>::new(2);
+        assert!(chain.most_common_starts(3).is_empty());
+        chain.train_string("The cat sat. The dog ran. A bird flew. The end. So it goes. A fin. Oh no.");
+        assert_eq!(chain.most_common_starts(1), vec![(String::from("The"), 3)]);
+        // ties are broken by the item
+        assert_eq!(chain.most_common_starts(3), vec![
+            (String::from("The"), 3),
+            (String::from("A"), 2),
+            (String::from("Oh"), 1),
+        ]);
+        assert_eq!(chain.most_common_starts(10).len(), 4);
+        assert!(chain.most_common_starts(0).is_empty());
+    }
+
+    #[test]
+    fn test_unigram_backoff() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 3, 4])
+            .train(vec![5, 6]);
+        // the item totals are only built once they're needed
+        assert!(chain.unigram.get().is_none());
+        let totals = chain.unigram().items.iter()
+            .enumerate()
+            .map(|(index, &item)| (item, chain.unigram().weight(index)))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(totals, hashmap!{1 => 2.0, 2 => 2.0, 3 => 2.0, 4 => 1.0, 5 => 1.0, 6 => 1.0});
+        // 6 is only ever trained last, so removing its node makes a dead end
+        chain.chain.remove(&vec![Some(6)]);
+        chain.rebuild_caches();
+        assert!(chain.unigram.get().is_none());
+        assert_eq!(chain.generate_from(&[5], -1), vec![5, 6]);
+        assert_eq!(chain.generate_from(&[9], -1), vec![9]);
+        assert!(!chain.unigram_backoff());
+        chain.set_unigram_backoff(true);
+
+        let mut continued = HashSet::new();
+        for _ in 0 .. 100 {
+            let sequence = chain.generate_from(&[5], -1);
+            assert!(sequence.len() > 2);
+            assert_eq!(&sequence[.. 2], &[5, 6]);
+            // 6 has no node, so it is never backed off to
+            assert_ne!(sequence[2], 6);
+            continued.insert(sequence[2]);
+        }
+        assert!(continued.contains(&1) && continued.contains(&2));
+        // training throws the totals away again
+        chain.train(vec![7]);
+        assert!(chain.unigram.get().is_none());
+        // the order has to fit too, so nothing is followed past 9 here
+        let mut chain = Chain::<u32>::new(2).with_unigram_backoff(true);
+        chain.train(vec![1, 2, 3]);
+        assert_eq!(chain.generate_from(&[9, 9], -1), vec![9, 9]);
+        // only 2 fits after 1, which the first few draws can easily miss
+        for _ in 0 .. 100 {
+            assert_eq!(chain.generate_from(&[9, 1], -1), vec![9, 1, 2, 3]);
+        }
+        // the totals are rebuilt after deserializing
+        #[cfg(feature = "serde_json")]
+        {
+            let de = Chain::<u32>::from_json(&chain.to_json().unwrap()).unwrap();
+            // backing off is a setting, so it isn't saved
+            assert!(!de.unigram_backoff());
+            let de = de.with_unigram_backoff(true);
+            assert_eq!(de.generate_from(&[9, 1], -1), vec![9, 1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_restrict_vocabulary() {
+        let mut chain = Chain::<String>::new(2);
+        chain.train_string("the cat sat on the mat. the rat sat on the cat. a bad rat ran.");
+        let allowed = chain.vocabulary()
+            .into_iter()
+            .filter(|&word| word != "rat")
+            .cloned()
+            .collect::<HashSet<_>>();
+        chain.restrict_vocabulary(&allowed);
+        assert!(!chain.vocabulary().contains(&String::from("rat")));
+        for (node, link) in chain.chain() {
+            assert!(!node.contains(&Some(String::from("rat"))));
+            assert!(!link.is_empty());
+            assert!(!link.contains_key(&Some(String::from("rat"))));
+        }
+        for _ in 0 .. 50 {
+            assert!(!chain.generate().contains(&String::from("rat")));
+        }
+        // the rest of the chain is untouched
+        assert_eq!(chain.transition_weight(&[Some(String::from("the")), Some(String::from("cat"))],
+                                           &Some(String::from("sat"))), 1);
+
+        chain.restrict_vocabulary(&HashSet::new());
+        assert!(chain.is_empty());
+        assert!(chain.generate().is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut chain = Chain::<u32>::new(2);
+        for i in 0 .. 100 {
+            chain.train(vec![i, i + 1, i + 2]);
+        }
+        chain.train(vec![1, 2, 3])
+            .prune(2);
+        assert!(!chain.is_empty());
+        let before = chain.clone();
+        chain.shrink_to_fit();
+        assert_eq!(chain, before);
+        assert_eq!(chain.recency, before.recency);
+        assert_eq!(chain.starts.len(), before.starts.len());
+        assert!(chain.chain.capacity() <= before.chain.capacity());
+    }
+
+    #[test]
+    fn test_retrain_order() {
+        let strings = vec![vec![1u32, 2, 3, 4, 2, 3, 5], vec![2, 3, 4, 1], vec![5, 5, 5, 5]];
+        let mut chain = Chain::new(3);
+        for string in &strings {
+            chain.train(string.clone());
+        }
+        for new_order in 1 ..= 3 {
+            let mut expected = Chain::new(new_order);
+            for string in &strings {
+                expected.train(string.clone());
+            }
+            let retrained = chain.retrain_order(new_order).unwrap();
+            assert_eq!(retrained, expected);
+            assert_eq!(retrained.starts.len(), expected.starts.len());
+        }
+        assert!(chain.retrain_order(0).is_none());
+        assert!(chain.retrain_order(4).is_none());
+
+        // the padding of strings shorter than the new order is lost
+        let mut chain = Chain::<u32>::new(3);
+        chain.train(vec![1]);
+        let retrained = chain.retrain_order(2).unwrap();
+        let mut expected = Chain::new(2);
+        expected.train(vec![1]);
+        assert!(expected.chain.contains_key(&vec![Some(1), None]));
+        expected.chain.remove(&vec![Some(1), None]);
+        assert_eq!(retrained, expected);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut chain = Chain::<u32>::with_capacity(2, 100);
+        assert!(chain.chain.capacity() >= 100);
+        assert!(chain.is_empty());
+        assert_eq!(chain.order(), 2);
+        let mut expected = Chain::new(2);
+        for string in &[vec![1, 2, 3, 4, 2, 3, 5], vec![2, 3, 4, 1], vec![5]] {
+            chain.train(string.clone());
+            expected.train(string.clone());
+        }
+        assert_eq!(chain, expected);
+        assert_eq!(chain.starts.len(), expected.starts.len());
+        assert_eq!(chain.generate_from(&[1, 2], 1), expected.generate_from(&[1, 2], 1));
+    }
+
+    #[test]
+    fn test_rebuild_at_order() {
+        let strings = vec![vec![1u32, 2, 3, 4, 2, 3, 5], vec![2, 3, 4, 1], vec![5, 5]];
+        let mut chain = Chain::new(1);
+        chain.extend(strings.clone());
+        chain.capitalize_sentences = true;
+        for order in 1 ..= 4 {
+            let rebuilt = chain.rebuild_at_order(order, strings.clone()).unwrap();
+            let mut expected = Chain::new(order);
+            expected.extend(strings.clone());
+            assert_eq!(rebuilt, expected);
+            assert_eq!(rebuilt.starts.len(), expected.starts.len());
+            assert!(rebuilt.capitalize_sentences);
+        }
+        assert_eq!(chain.rebuild_at_order(0, strings.clone()), Err(ChainError::ZeroOrder));
+        // without any strings, the new chain is empty
+        assert!(chain.rebuild_at_order(3, vec![]).unwrap().is_empty());
+        // nodes of the wrong length are never chosen from
+        let mut rng = rand::thread_rng();
+        assert!(chain.choose_random_link(&[Some(1), Some(2)], None, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_order2_training() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let link = test_get_link!(chain, [1u32, 2u32]);
+        test_link_weight!(link, Some(3u32), 1);
+
+        let link = test_get_link!(chain, [2u32, 3u32]);
+        test_link_weight!(link, None, 1);
+        test_link_weight!(link, Some(4u32), 1);
+
+        let link = test_get_link!(chain, [3u32, 4u32]);
+        test_link_weight!(link, None, 2);
+
+        let link = test_get_link!(chain, [1u32, 3u32]);
+        test_link_weight!(link, Some(4u32), 1);
+    }
+
+    #[test]
+    fn test_rarest_links() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![2, 3, 4])
+            .train(vec![1, 3, 4]);
+        let rarest = chain.rarest_links(3);
+        assert_eq!(rarest.len(), 3);
+        assert!(rarest.iter().all(|&(_, _, weight)| weight == 1));
+        let all = chain.rarest_links(100);
+        assert_eq!(all.len(), 8);
+        assert!(all.windows(2).all(|w| w[0].2 <= w[1].2));
+        assert_eq!(all.last().unwrap().2, 2);
+        assert!(chain.rarest_links(0).is_empty());
+        // ties are broken the same way however many links are asked for
+        let two = chain.rarest_links(2);
+        assert_eq!(&rarest[..2], &two[..]);
+        assert_eq!(&all[..3], &rarest[..]);
+    }
+
+    #[test]
+    fn test_generate_sentence_with_no_space() {
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("我 爱 猫 , 你 .");
+        assert_eq!(chain.generate_sentence_with(|_, _| String::new()), "我爱猫,你.");
+        assert_eq!(chain.generate_sentence(), "我 爱 猫, 你.");
+    }
+
+    #[test]
+    fn test_generate_graceful_dead_end() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3]);
+        // simulate pruning away every way out of the node [2]
+        chain.chain.remove(&vec![Some(2u32)]);
+        for _ in 0 .. 20 {
+            let (items, termination) = chain.generate_graceful(-1);
+            match items.last() {
+                Some(&2) => assert_eq!(termination, Termination::DeadEnd),
+                // starting from the empty start node yields nothing
+                Some(&3) | None => assert_eq!(termination, Termination::Terminal),
+                other => panic!("unexpected end of sequence: {:?}", other),
+            }
+        }
+        let (items, termination) = Chain::<u32>::new(1).generate_graceful(-1);
+        assert!(items.is_empty());
+        assert_eq!(termination, Termination::DeadEnd);
+    }
+
+    #[test]
+    fn test_train_reverse() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train_reverse(vec![1, 2, 3, 4]);
+        test_link_weight!(test_get_link!(chain, [4, 3]), Some(2), 1);
+        test_link_weight!(test_get_link!(chain, [2, 1]), None, 1);
+        assert!(!chain.chain.contains_key(&vec![Some(1), Some(2)]));
+        for _ in 0 .. 10 {
+            let generated = chain.generate_reverse();
+            assert!(generated == vec![1, 2, 3, 4] || generated == vec![1, 2]
+                    || generated == vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_train_bidirectional() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train_bidirectional(vec![1, 2, 3]);
+        let link = test_get_link!(chain, [1u32]);
+        test_link_weight!(link, Some(2u32), 1);
+        test_link_weight!(link, None, 1);
+
+        let link = test_get_link!(chain, [2u32]);
+        test_link_weight!(link, Some(1u32), 1);
+        test_link_weight!(link, Some(3u32), 1);
+
+        let link = test_get_link!(chain, [3u32]);
+        test_link_weight!(link, Some(2u32), 1);
+        test_link_weight!(link, None, 1);
+    }
+
+    #[test]
+    fn test_merge_weighted() {
+        let mut chain1 = Chain::<u32>::new(1);
+        chain1.train(vec![1, 2, 3]);
+        let mut chain2 = Chain::<u32>::new(1);
+        chain2.train(vec![1, 2, 4])
+            .train(vec![1, 2, 4]);
+        let mut merged = chain1.clone();
+        merged.merge_weighted(&chain2, 2.0);
+
+        let link = test_get_link!(merged, [2u32]);
+        test_link_weight!(link, Some(3u32), 1);
+        test_link_weight!(link, Some(4u32), 4);
+        let link = test_get_link!(merged, [1u32]);
+        test_link_weight!(link, Some(2u32), 5);
+        let link = test_get_link!(merged, [4u32]);
+        test_link_weight!(link, None, 4);
+
+        // small factors round, but keep every link
+        let mut merged = chain1.clone();
+        merged.merge_weighted(&chain2, 0.1);
+        test_link_weight!(test_get_link!(merged, [2u32]), Some(4u32), 1);
+        let mut merged = chain1.clone();
+        merged.merge_weighted(&chain2, 1.0);
+        assert_eq!(merged, chain1.clone().merge(&chain2).clone());
+        let mut merged = chain1.clone();
+        merged.merge_weighted(&chain2, 0.0);
+        assert_eq!(merged, chain1);
+
+        // fractional weights are scaled exactly
+        let mut chain = Chain::<u32, f64>::with_weights(1);
+        chain.train(vec![1, 2]);
+        let mut other = chain.clone();
+        other.merge_weighted(&chain, 0.25);
+        test_link_weight!(test_get_link!(other, [1u32]), Some(2u32), 1.25);
+    }
+
+    #[test]
+    fn test_merge_max() {
+        let mut chain1 = Chain::<u32>::new(1);
+        chain1.train(vec![1, 2, 3])
+            .train(vec![1, 2, 3]);
+        let mut chain2 = Chain::<u32>::new(1);
+        chain2.train(vec![1, 2, 4]);
+        chain1.merge_max(&chain2);
+
+        let link = test_get_link!(chain1, [2u32]);
+        test_link_weight!(link, Some(3u32), 2);
+        test_link_weight!(link, Some(4u32), 1);
+
+        let link = test_get_link!(chain1, [1u32]);
+        test_link_weight!(link, Some(2u32), 2);
+
+        let link = test_get_link!(chain1, [4u32]);
+        test_link_weight!(link, None, 1);
+    }
+
+    #[test]
+    fn test_generate_scored() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 4]);
+        for _ in 0 .. 20 {
+            let (items, score) = chain.generate_scored(-1);
+            let expected = match items.first() {
+                // 1 -> 2 -> {3, 4} -> end
+                Some(&1) => 0.5f64.ln(),
+                // 2 -> {3, 4} -> end
+                Some(&2) => 0.5f64.ln(),
+                // 3 -> end, 4 -> end
+                Some(&3) | Some(&4) => 0.0,
+                // the start node yields nothing
+                None => 0.0,
+                other => panic!("unexpected start of sequence: {:?}", other),
+            };
+            assert!((score - expected).abs() < 1e-9, "{:?} scored {}", items, score);
+        }
+    }
+
+    #[test]
+    fn test_expected_length() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3]);
+        assert!((chain.expected_length(&[]) - 3.0).abs() < 1e-6);
+        assert!((chain.expected_length(&[3]) - 0.0).abs() < 1e-6);
+        assert_eq!(chain.expected_length(&[5]), 0.0);
+
+        // 1 -> 1 with p = 1/2, 1 -> end with p = 1/2: geometric, mean of 1
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 1]);
+        assert!((chain.expected_length(&[1]) - 1.0).abs() < 1e-6);
+
+        // a loop with no way out never terminates
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2]);
+        chain.chain.insert(vec![Some(2)], hashmap!{Some(1) => 1});
+        assert_eq!(chain.expected_length(&[1]), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_train_chars() {
+        let mut chain = Chain::<char>::new(2);
+        assert_eq!(chain.generate_string(), "");
+        let words = ["banana", "bandana", "cabana", "canal", "panama", "anna"];
+        for word in &words {
+            chain.train_chars(word);
+        }
+        assert_eq!(chain.transition_weight(&[Some('a'), Some('n')], &Some('a')), 6);
+        let alphabet = words.iter()
+            .flat_map(|word| word.chars())
+            .collect::<HashSet<_>>();
+        for _ in 0 .. 50 {
+            let word = chain.generate_string();
+            assert!(!word.is_empty());
+            assert!(word.chars().all(|c| alphabet.contains(&c)));
+            // every pair of letters was seen in some word
+            for pair in word.chars().collect::<Vec<_>>().windows(2) {
+                assert!(words.iter().any(|w| w.contains(&pair.iter().collect::<String>())));
+            }
+        }
+        // multibyte characters are whole items
+        let mut chain = Chain::<char>::new(1);
+        chain.train_chars("äö");
+        assert_eq!(chain.generate_greedy(&['ä'], -1).into_iter().collect::<String>(), "äö");
+    }
+
+    #[test]
+    fn test_detokenize() {
+        fn words(list: &[&str]) -> Vec<String> {
+            list.iter().map(|w| w.to_string()).collect()
+        }
+        assert_eq!(detokenize(&[]), "");
+        assert_eq!(detokenize(&words(&["Hello", ",", "world", "!"])), "Hello, world!");
+        assert_eq!(detokenize(&words(&["\"", "Hi", "\"", "she", "said", "."])), "\"Hi\" she said.");
+        assert_eq!(detokenize(&words(&["a", "(", "b", ")", "[", "c", "]", "d"])), "a (b) [c] d");
+        assert_eq!(detokenize(&words(&["\"Go", "home", "!\"", "he", "said"])), "\"Go home!\" he said");
+        assert_eq!(detokenize(&words(&["It", "-", "works"])), "It - works");
+        assert_eq!(detokenize(&words(&["Wait", "--", "what", "?!"])), "Wait -- what?!");
+        // other runs of punctuation attach to the word before them
+        assert_eq!(detokenize(&words(&["So", "...", "yes", ",", "fine", "!!"])), "So... yes, fine!!");
+        assert_eq!(detokenize(&words(&["a", "\"", "b", "-\"", "c"])), "a \"b-\" c");
+        // opening quotes still attach to the word after them
+        assert_eq!(detokenize(&words(&["He", "said", "\"", "no", "\"", "twice"])), "He said \"no\" twice");
+        assert_eq!(detokenize(&words(&["He", "said", "\"...", "no", "\""])), "He said \"...no\"");
+
+        // tokens as the tokenizer splits them come back together
+        let breaks = default_break_tokens();
+        for text in &["Wait -- what?! No...", "He said \"hi\" - twice.", "Yes!!! Fine, ok?"] {
+            assert_eq!(detokenize(&tokenize(text, &breaks)), *text);
+        }
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("Wait -- what?!");
+        assert_eq!(chain.generate_sentence(), "Wait -- what?!");
+    }
+
+    #[test]
+    fn test_tokenize_unicode() {
+        fn words(list: &[&str]) -> Vec<String> {
+            list.iter().map(|w| w.to_string()).collect()
+        }
+        let breaks = default_break_tokens();
+        // plain ASCII splits as it always has
+        assert_eq!(tokenize("\"Go home!\" he said, ok", &breaks),
+                   words(&["\"Go", "home", "!\"", "he", "said", ",", "ok"]));
+        assert_eq!(tokenize("“Wait—what?” she asked…", &breaks),
+                   words(&["“", "Wait", "—", "what", "?”", "she", "asked", "…"]));
+        assert_eq!(tokenize("Привет, мир! 你好。", &breaks), words(&["Привет", ",", "мир", "!", "你好", "。"]));
+        assert_eq!(tokenize("a\u{a0}b", &breaks), words(&["a", "b"]));
+        assert_eq!(detokenize(&words(&["“", "Wait", "—", "what", "?”", "she", "asked", "…"])),
+                   "“Wait—what?” she asked…");
+        assert_eq!(detokenize(&words(&["pages", "3", "–", "5", "—", "or", "so"])), "pages 3–5—or so");
+
+        // curly quotes and Unicode terminators end sentences
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("“Is it raining?” she asked. It is。");
+        assert!(breaks.contains("?”") && breaks.contains("。"));
+        assert_eq!(chain.transition_weight(&[Some(String::from("?”"))], &None), 1);
+        assert_eq!(chain.transition_weight(&[Some(String::from("。"))], &None), 1);
+        let sentence = chain.generate_sentence();
+        assert!(["“Is it raining?”", "she asked.", "It is。"].contains(&sentence.as_str()), "{}", sentence);
+    }
+
+    #[test]
+    fn test_train_sampled() {
+        let mut rng = rand::thread_rng();
+        let mut full = Chain::<u32>::new(2);
+        full.train(vec![1, 2, 3, 4, 1, 2]);
+        let mut sampled = Chain::<u32>::new(2);
+        sampled.train_sampled(vec![1, 2, 3, 4, 1, 2], 1.0, &mut rng);
+        assert_eq!(sampled, full);
+
+        let mut sampled = Chain::<u32>::new(2);
+        sampled.train_sampled(vec![1, 2, 3, 4, 1, 2], 0.0, &mut rng);
+        assert!(sampled.is_empty());
+        assert!(sampled.recency.is_empty());
+        assert_eq!(sampled.clock, 0);
+    }
+
+    #[test]
+    fn test_generate_filtered() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3, 4])
+            .train(vec![1, 3, 2, 4])
+            .train(vec![3, 3, 3]);
+        for _ in 0 .. 50 {
+            let sequence = chain.generate_filtered(-1, |&x| x != 3);
+            assert!(!sequence.contains(&3));
+        }
+        assert!(chain.generate_filtered(-1, |_| false).is_empty());
+
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("the cat sat on the mat. the dog sat on the cat.");
+        for _ in 0 .. 50 {
+            let sentence = chain.generate_sentence_filtered(|w| w != "cat");
+            assert!(!sentence.contains("cat"));
+        }
+    }
+
+    #[test]
+    fn test_generate_avoiding() {
+        use rand::{SeedableRng, StdRng};
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string("the cat sat on the mat. the dog sat on the cat. a cat ran.");
+        let avoid = hashset!{String::from("cat"), String::from("mat")};
+        let mut rng = rand::thread_rng();
+        for _ in 0 .. 200 {
+            let sequence = chain.generate_avoiding(&avoid, &mut rng, 30);
+            assert!(sequence.iter().all(|word| !avoid.contains(word)));
+        }
+        // the chain itself is unchanged
+        assert_eq!(chain.transition_weight(&[Some(String::from("the"))], &Some(String::from("cat"))), 2);
+        assert!(chain.generate_avoiding(&chain.vocabulary().into_iter().cloned().collect(), &mut rng, -1)
+            .is_empty());
+        // the same seed avoids the same way
+        for seed in 0 .. 10 {
+            // avoiding words can leave loops with no way out, so limit it
+            assert_eq!(chain.generate_avoiding(&avoid, &mut StdRng::from_seed(&[seed][..]), 30),
+                       chain.generate_avoiding(&avoid, &mut StdRng::from_seed(&[seed][..]), 30));
+        }
+    }
+
+    struct XorShift(u32);
+
+    impl RandomSource for XorShift {
+        fn next_u32(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_probability_smoothed() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 1, 3]);
+        assert_eq!(chain.vocabulary_size(), 3);
+        // 1, 2, 3 and the end
+        let vocab_size = chain.vocabulary_size() + 1;
+        let unseen = chain.probability_smoothed(&[Some(2)], &Some(3), 1.0, vocab_size);
+        assert!(unseen > 0.0 && unseen < chain.probability_smoothed(&[Some(2)], &Some(1), 1.0, vocab_size));
+        assert_eq!(unseen, 1.0 / 5.0);
+        assert_eq!(chain.probability_smoothed(&[Some(1)], &Some(2), 0.5, vocab_size), 1.5 / 4.0);
+        // without smoothing, this is the plain probability
+        assert_eq!(chain.probability_smoothed(&[Some(1)], &Some(2), 0.0, vocab_size),
+                   chain.probability(&[Some(1)], &Some(2)));
+        // an unknown node spreads its probability evenly
+        assert_eq!(chain.probability_smoothed(&[Some(9)], &Some(1), 1.0, vocab_size), 0.25);
+        assert_eq!(chain.probability_smoothed(&[Some(9)], &Some(1), 0.0, vocab_size), 0.0);
+    }
+
+    #[test]
+    fn test_transition_entropy() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 4])
+            .train(vec![5, 6, 5, 7, 5, 8, 5]);
+        assert_eq!(chain.transition_entropy(&[Some(1)]), 0.0);
+        // a uniform two-way branch
+        assert_eq!(chain.transition_entropy(&[Some(2)]), 1.0);
+        // a uniform four-way branch, with the end as one of the ways
+        assert_eq!(chain.transition_entropy(&[Some(5)]), 2.0);
+        assert_eq!(chain.transition_entropy(&[Some(9)]), 0.0);
+        // the start node has weights 2 and 1
+        let p: f64 = 2.0 / 3.0;
+        let start = -(p * p.log2() + (1.0 - p) * (1.0 - p).log2());
+        assert!((chain.transition_entropy(&[None]) - start).abs() < 1e-12);
+
+        // only [2], [5] and [None] are uncertain, out of 16 observations
+        let expected = (2.0 * 1.0 + 4.0 * 2.0 + 3.0 * start) / 16.0;
+        assert!((chain.average_entropy() - expected).abs() < 1e-12);
+        assert_eq!(Chain::<u32>::new(1).average_entropy(), 0.0);
+    }
+
+    #[test]
+    fn test_log_probability() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 4, 5])
+            .train(vec![1, 2, 4, 3, 5]);
+        // (weight + 1) / (total + 6), for 1 to 5 and the end
+        let expected = 2.0 * (3.0f64 / 8.0).ln() + (2.0f64 / 8.0).ln() + 3.0 * (2.0f64 / 7.0).ln();
+        assert!((chain.log_probability(&[1, 2, 3, 4, 5]) - expected).abs() < 1e-9);
+
+        let trained = chain.log_probability(&[1, 2, 3, 4, 5]);
+        let random = chain.log_probability(&[4, 1, 5, 2, 3]);
+        assert!(random.is_finite());
+        assert!(trained > random);
+        assert!(chain.perplexity(&[1, 2, 3, 4, 5]) < chain.perplexity(&[4, 1, 5, 2, 3]));
+        assert!((chain.perplexity(&[1, 2, 3, 4, 5]) - (-trained / 6.0).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_links() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 1, 2, 4]);
+        let links = chain.links(&[Some(1), Some(2)]).unwrap();
+        assert_eq!(links, &hashmap!{Some(3) => 1, Some(4) => 1});
+        assert_eq!(chain.links(&[None, None]).unwrap(), &hashmap!{Some(1) => 1});
+        assert!(chain.links(&[Some(4), Some(1)]).is_none());
+        assert_eq!(chain.transition_weight(&[None, Some(1)], &Some(2)), 1);
+        assert_eq!(chain.transition_weight(&[Some(2), Some(4)], &None), 1);
+        assert_eq!(chain.transition_weight(&[Some(2), Some(4)], &Some(1)), 0);
+        assert_eq!(chain.transition_weight(&[Some(9), Some(9)], &None), 0);
+    }
+
+    #[test]
+    fn test_probability() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 1, 3, 1, 2]);
+        assert_eq!(chain.total_weight(&[Some(1)]), 3);
+        assert_eq!(chain.total_weight(&[Some(9)]), 0);
+        assert_eq!(chain.probability(&[Some(1)], &Some(3)), 1.0 / 3.0);
+        assert_eq!(chain.probability(&[Some(2)], &Some(1)), 0.5);
+        assert_eq!(chain.probability(&[Some(2)], &None), 0.5);
+        assert_eq!(chain.probability(&[Some(3)], &None), 0.0);
+        assert_eq!(chain.probability(&[Some(9)], &None), 0.0);
+        // a node left without links
+        chain.chain.insert(vec![Some(9)], HashMap::new());
+        assert_eq!(chain.probability(&[Some(9)], &None), 0.0);
+    }
+
+    #[test]
+    fn test_most_likely_next() {
+        let mut chain = Chain::new(1);
+        chain.train(vec![1, 2, 1, 3, 1, 2]);
+        assert_eq!(chain.most_likely_next(&[Some(1)]), Some(&2));
+        // the end is never the most likely item, even when it's heaviest
+        assert_eq!(chain.most_likely_next(&[Some(2)]), Some(&1));
+        assert_eq!(chain.most_likely_next(&[Some(4)]), None);
+        // ties go to the smallest item, whatever order they were trained in
+        let mut chain = Chain::new(1);
+        chain.train(vec![5, 9, 5, 7, 5, 8]);
+        assert_eq!(chain.most_likely_next(&[Some(5)]), Some(&7));
     }
 
-    /// Generates a string of items with no maximum limit.
-    /// This is equivalent to `generate_limit(-1)`.
-    pub fn generate(&self) -> Vec<T> {
-        self.generate_limit(-1)
+    #[test]
+    fn test_generate_greedy() {
+        let mut chain = Chain::new(1);
+        chain.train(vec![1, 2, 3, 1, 2, 3, 4]);
+        // the greedy path loops from 3 back to 1
+        assert_eq!(chain.generate_greedy(&[], -1), vec![1, 2, 3]);
+        assert_eq!(chain.generate_greedy(&[], 7), vec![1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(chain.generate_greedy(&[9, 3], -1), vec![9, 3, 1, 2]);
+        assert_eq!(chain.generate_greedy(&[4], -1), vec![4]);
+        for _ in 0 .. 10 {
+            assert_eq!(chain.generate_greedy(&[2], 5), vec![2, 3, 1, 2, 3]);
+        }
+        assert_eq!(chain.generate_greedy(&[9, 3, 1], 2), vec![9, 3]);
     }
 
-    /// Generates a string of items, based on the training, of up to N items.
-    /// Specifying a maximum of -1 allows any arbitrary size of list.
-    pub fn generate_limit(&self, max: isize) -> Vec<T> {
-        // TODO : DRY generate_sentence(1)
-        if self.chain.is_empty() {
-            return vec![];
+    #[test]
+    fn test_iter_generate() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 4]);
+        for _ in 0 .. 10 {
+            let sequence = chain.iter_generate().collect::<Vec<_>>();
+            assert!(sequence == vec![1, 2, 3, 4] || sequence == vec![2, 3, 4] || sequence == vec![3, 4]);
         }
+        assert_eq!(Chain::<u32>::new(2).iter_generate().next(), None);
 
-        let mut curs = {
-            let c;
-            loop {
-                if let Some(n) = self.choose_random_node() {
-                    c = n.clone();
-                    break;
-                }
-            }
-            c
+        // a loop never ends on its own
+        let mut chain = Chain::<u32>::new(1);
+        chain.chain = hashmap!{
+            vec![Some(1)] => hashmap!{Some(2) => 1},
+            vec![Some(2)] => hashmap!{Some(1) => 1},
         };
+        chain.rebuild_caches();
+        let sequence = chain.iter_generate().take(50).collect::<Vec<_>>();
+        assert_eq!(sequence.len(), 50);
+        assert!(sequence.windows(2).all(|w| w[0] != w[1]));
+    }
 
-        // this takes care of an instance where we have order N and have chosen a node that is
-        // shorter than our order.
-        if curs.iter().find(|x| x.is_none()).is_some() {
-            return curs.iter()
-                .cloned()
-                .filter_map(|x| x)
-                .collect();
+    #[test]
+    fn test_generate_bounded() {
+        let mut chain = Chain::new(1);
+        chain.train(vec![1, 2])
+            .train(vec![3, 4, 5, 6, 7, 8]);
+        // starting anywhere in 1, 2 or 7, 8 gives too few items, so those
+        // attempts are restarted
+        for _ in 0 .. 20 {
+            let sequence = chain.generate_bounded(3, 0);
+            assert!(sequence.len() >= 3);
+            assert!(sequence.ends_with(&[8]));
         }
+        for _ in 0 .. 20 {
+            let len = chain.generate_bounded(2, 3).len();
+            assert!((2 ..= 3).contains(&len));
+        }
+        // no attempt can reach the minimum, so the longest one is returned
+        assert_eq!(chain.generate_bounded(10, 0), vec![3, 4, 5, 6, 7, 8]);
+        assert!(Chain::<u32>::new(1).generate_bounded(1, 0).is_empty());
+    }
 
-        let mut result = curs.clone()
-            .into_iter()
-            .map(|x| x.unwrap())
-            .collect::<Vec<T>>();
-
-        loop {
-            // Choose the next item
-            let next = self.choose_random_link(&curs);
-            if let Some(next) = next {
-                result.push(next.clone());
-                curs.push(Some(next.clone()));
-                curs.remove(0);
-            }
-            else {
-                break;
-            }
+    #[test]
+    fn test_generate_from() {
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 4]);
+        assert_eq!(chain.generate_from(&[9, 1, 2], -1), vec![9, 1, 2, 3, 4]);
+        // a short prefix is the start of a sequence
+        assert_eq!(chain.generate_from(&[1], -1), vec![1, 2, 3, 4]);
+        assert_eq!(chain.generate_from(&[2], -1), vec![2]);
+        // nothing follows
+        assert_eq!(chain.generate_from(&[4, 1], -1), vec![4, 1]);
+        assert_eq!(chain.generate_from(&[1, 2], 3), vec![1, 2, 3]);
+        // the prefix counts towards the maximum
+        assert_eq!(chain.generate_from(&[9, 1, 2], 2), vec![9, 1]);
+        assert_eq!(chain.generate_from(&[9, 1, 2], 0), vec![9, 1, 2, 3, 4]);
+    }
 
-            if result.len() as isize >= max && max > 0 {
-                break;
-            }
+    #[test]
+    fn test_starts_index() {
+        let complete_nodes = |chain: &Chain<u32>| {
+            chain.chain.keys()
+                .filter(|node| node.iter().all(Option::is_some))
+                .cloned()
+                .collect::<HashSet<_>>()
+        };
+        let mut chain = Chain::<u32>::new(2);
+        let mut x = 1u32;
+        for _ in 0 .. 2_000 {
+            let string = (0 .. 10)
+                .map(|_| {
+                    x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (x >> 16) % 50
+                })
+                .collect();
+            chain.train(string);
         }
-        result
+        let starts = chain.starts.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(starts.len(), chain.starts.len());
+        assert_eq!(starts, complete_nodes(&chain));
+        let mut rng = rand::thread_rng();
+        for _ in 0 .. 1_000 {
+            let node = chain.choose_random_node(&mut rng).unwrap();
+            assert!(chain.chain.contains_key(node));
+        }
+
+        chain.prune_nodes(2);
+        assert_eq!(chain.starts.iter().cloned().collect::<HashSet<_>>(), complete_nodes(&chain));
+        let mut merged = Chain::new(2);
+        merged.merge(&chain);
+        assert_eq!(merged.starts.len(), chain.starts.len());
     }
 
-    fn choose_random_link(&self, node: &Node<T>) -> Option<&T> {
-        assert_eq!(node.len(), self.order);
-        if let Some(ref link) = self.chain.get(node) {
-            let mut weights = link.iter()
-                .map(|(k, v)| Weighted { weight: *v, item: k.as_ref() })
-                .collect::<Vec<_>>();
-            let chooser = WeightedChoice::new(&mut weights);
-            let mut rng = rand::thread_rng();
-            chooser.ind_sample(&mut rng)
+    #[test]
+    fn test_generate_short_training() {
+        // every node has padding when the sequence is shorter than the order
+        let mut chain = Chain::<u32>::new(3);
+        chain.train(vec![1, 2]);
+        for _ in 0 .. 50 {
+            assert_eq!(chain.generate(), vec![1, 2]);
         }
-        else {
-            None
+
+        // complete nodes are preferred as starting points
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3]);
+        for _ in 0 .. 50 {
+            let sequence = chain.generate();
+            assert!(sequence == vec![1, 2, 3] || sequence == vec![2, 3]);
         }
     }
 
-    fn choose_random_node(&self) -> Option<&Node<T>> {
-        if self.chain.is_empty() {
-            None
+    #[test]
+    fn test_generate_with_temperature() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.chain = hashmap!{
+            vec![Some(1)] => hashmap!{Some(2) => 3, Some(3) => 1},
+            vec![Some(2)] => hashmap!{None => 1},
+            vec![Some(3)] => hashmap!{None => 1},
+        };
+        for _ in 0 .. 100 {
+            assert_ne!(chain.generate_with_temperature(0.0, -1), vec![1, 3]);
+            assert_ne!(chain.generate_with_temperature(0.01, -1), vec![1, 3]);
         }
-        else {
-            let mut rng = rand::thread_rng();
-            self.chain.keys()
-                .nth(rng.gen_range(0, self.chain.len()))
+        let mut uniform = false;
+        for _ in 0 .. 300 {
+            uniform |= chain.generate_with_temperature(100.0, -1) == vec![1, 3];
         }
+        assert!(uniform);
+        assert!(Chain::<u32>::new(1).generate_with_temperature(1.0, -1).is_empty());
     }
-}
 
-lazy_static! { 
-    /// Symbol combinations to break sentences on.
-    static ref BREAK: [&'static str; 7] = [".", "?", "!", ".\"", "!\"", "?\"", ",\""];
-}
-/// String-specific implementation of the chain. Contains some special string-
-/// specific functions.
-impl Chain<String> {
-    /// Trains this chain on a single string. Strings are broken into words,
-    /// which are split by whitespace and punctuation.
-    pub fn train_string(&mut self, sentence: &str) -> &mut Self {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(
-                r#"[^ .!?,\-\n\r\t]+|[.,!?\-"]+"#
-                ).unwrap();
-        };
-        let parts = {
-            let mut parts = Vec::new();
-            let mut words = Vec::new();
-            for mat in RE.find_iter(sentence).map(|m| m.as_str()) {
-                words.push(String::from(mat));
-                if BREAK.contains(&mat) {
-                    parts.push(words.clone());
-                    words.clear();
+    #[test]
+    fn test_generate_limit() {
+        for order in 1 .. 4 {
+            let mut chain = Chain::<u32>::new(order);
+            chain.train((0 .. 20).collect())
+                .train(vec![5, 4, 3, 2, 1, 0]);
+            for max in 1 .. 8 {
+                for _ in 0 .. 20 {
+                    assert!(chain.generate_limit(max).len() <= max as usize);
+                    assert!(chain.generate_top_k(1, max).len() <= max as usize);
+                    assert!(Chain::generate_alternating(&chain, &chain, 1, max).len() <= max as usize);
+                    let (result, termination) = chain.generate_graceful(max);
+                    assert!(result.len() <= max as usize);
+                    if result.len() < max as usize {
+                        assert_ne!(termination, Termination::Limit);
+                    }
                 }
             }
-            if !words.is_empty() {
-                parts.push(words);
+            // a long walk through the chain reaches the limit exactly
+            let mut chain = Chain::<u32>::new(order);
+            chain.train((0 .. 20).collect());
+            assert_eq!(chain.generate_from(&[0], 10).len(), 10);
+            assert_eq!(chain.generate_limit(0).last(), Some(&19));
+        }
+    }
+
+    #[test]
+    fn test_generate_distinct() {
+        // only [1, 2], [2] and [3] can be generated
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2])
+            .train(vec![3]);
+        let sequences = chain.generate_distinct(10, -1);
+        assert!(sequences.len() <= 3);
+        assert_eq!(sequences.iter().collect::<HashSet<_>>().len(), sequences.len());
+        assert!(sequences.iter().all(|s| s == &[1, 2] || s == &[2] || s == &[3]));
+
+        let mut chain = Chain::<u32>::new(1);
+        chain.train((0 .. 50).collect());
+        assert_eq!(chain.generate_distinct(5, -1).len(), 5);
+        assert!(chain.generate_distinct(0, -1).is_empty());
+        assert!(Chain::<u32>::new(1).generate_distinct(5, -1).is_empty());
+    }
+
+    #[test]
+    fn test_sample_next() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.update_link_weight(&[Some(1)], &Some(2), 3);
+        chain.update_link_weight(&[Some(1)], &Some(3), 1);
+        let mut rng = rand::thread_rng();
+        let mut seen = HashSet::new();
+        for _ in 0 .. 200 {
+            let (next, p) = chain.sample_next(&[Some(1)], &mut rng).unwrap();
+            match next {
+                Some(2) => assert!((p - 0.75).abs() < 1e-12),
+                Some(3) => assert!((p - 0.25).abs() < 1e-12),
+                _ => panic!("unexpected link {:?}", next),
             }
-            parts
+            seen.insert(next);
+        }
+        assert_eq!(seen.len(), 2);
+        assert_eq!(chain.sample_next(&[Some(2)], &mut rng), None);
+        assert_eq!(chain.sample_next(&[Some(1), Some(1)], &mut rng), None);
+
+        chain.train(vec![4]);
+        assert_eq!(chain.sample_next(&[Some(4)], &mut rng), Some((None, 1.0)));
+    }
+
+    #[test]
+    fn test_generate_top_p() {
+        let mut chain = Chain::<u32>::new(1);
+        for &(next, weight) in &[(2, 6), (3, 3), (4, 1)] {
+            chain.update_link_weight(&[Some(1)], &Some(next), weight);
+        }
+        let node = vec![Some(1)];
+        let chosen = |p: f64| {
+            let sampling = Sampling { top_p: p, ..Sampling::default() };
+            let mut rng = rand::thread_rng();
+            (0 .. 500)
+                .map(|_| chain.choose_random_link_sampled(&node, None, sampling, &mut rng).unwrap().0.unwrap())
+                .collect::<HashSet<_>>()
         };
-        for string in parts {
-            self.train(string);
+        // the pool grows until its probability reaches p
+        assert_eq!(chosen(0.5), hashset!{2});
+        assert_eq!(chosen(0.6), hashset!{2});
+        assert_eq!(chosen(0.8), hashset!{2, 3});
+        assert_eq!(chosen(0.95), hashset!{2, 3, 4});
+        // edge cases: all links, or just the heaviest
+        assert_eq!(chosen(1.0), hashset!{2, 3, 4});
+        assert_eq!(chosen(0.0), hashset!{2});
+        assert_eq!(chosen(0.001), hashset!{2});
+
+        // a tiny p follows the heaviest link, like greedy generation
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3, 1, 2, 3, 4])
+            .train(vec![2, 3, 1, 5])
+            .train(vec![3, 1, 2]);
+        for _ in 0 .. 100 {
+            let sequence = chain.generate_top_p(0.01, 20);
+            assert_eq!(chain.generate_greedy(&sequence[.. 1], 20), sequence);
         }
-        self
+        assert!(Chain::<u32>::new(1).generate_top_p(0.5, -1).is_empty());
     }
 
-    /// Generates a sentence, which are ended by "break" strings or null links.
-    /// "Break" strings are:
-    /// `.`, `?`, `!`, `."`, `!"`, `?"`, `,"`
-    pub fn generate_sentence(&self) -> String {
-        // TODO : DRY generate_sentence(1)
-        // consider an iterator?
-        if self.chain.is_empty() {
-            return String::new();
+    #[test]
+    fn test_generate_top_k() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3, 1, 2, 3, 4])
+            .train(vec![2, 3, 1, 5])
+            .train(vec![3, 1, 2]);
+        for _ in 0 .. 100 {
+            // k = 1 always follows the heaviest link, like greedy generation
+            let sequence = chain.generate_top_k(1, 20);
+            assert_eq!(chain.generate_greedy(&sequence[.. 1], 20), sequence);
         }
 
-        let mut curs = vec!(None; self.order);
-        let mut result = Vec::new();
-        loop {
-            // Choose the next item
-            let next = self.choose_random_link(&curs);
-            if let Some(next) = next {
-                result.push(next.clone());
-                curs.push(Some(next.clone()));
-                curs.remove(0);
-                if BREAK.contains(&next.as_str()) {
-                    break;
-                }
-            }
-            else {
-                break;
-            }
+        // ties are always broken the same way
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2])
+            .train(vec![1, 3]);
+        let cursor = chain.cursor_from(&[1]);
+        let sampling = Sampling { top_k: 1, ..Sampling::default() };
+        let sequences = (0 .. 100)
+            .map(|_| chain.extend_from_node(cursor.clone(), vec![], -1, None, sampling, &mut rand::thread_rng()).0)
+            .collect::<HashSet<_>>();
+        assert_eq!(sequences.len(), 1);
+        assert!(Chain::<u32>::new(1).generate_top_k(1, -1).is_empty());
+    }
+
+    #[test]
+    fn test_generate_with_rng() {
+        use rand::{SeedableRng, StdRng};
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3, 1, 2, 4, 1, 3, 2, 1, 2, 3, 4])
+            .train(vec![2, 3, 1, 2, 4, 4, 1]);
+        for seed in 0 .. 20 {
+            let first = chain.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), 100);
+            let second = chain.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), 100);
+            assert_eq!(first, second);
         }
-        let mut result = result.into_iter()
-            .fold(String::new(), |a, b| if BREAK.contains(&b.as_str()) || b == "," { a + b.as_str() } else { a + " " + b.as_str() });
-        result.remove(0); // get rid of the leading space character
-        result
     }
 
-    /// Generates a paragraph of N sentences. Each sentence is broken off by N
-    /// spaces.
-    pub fn generate_paragraph(&self, sentences: usize) -> String {
-        let mut paragraph = Vec::new();
-        for _ in 0 .. sentences {
-            paragraph.push(self.generate_sentence());
+    #[test]
+    fn test_generate_with_source() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3, 1, 3, 2, 1, 2, 2, 3, 1])
+            .train(vec![3, 2, 1, 1, 2, 3]);
+        for seed in 1 .. 20 {
+            let first = chain.generate_with_source(&mut XorShift(seed), 50);
+            let second = chain.generate_with_source(&mut XorShift(seed), 50);
+            assert_eq!(first, second);
         }
-        paragraph.join(" ")
+        // any rand::Rng works too
+        chain.generate_with_source(&mut rand::thread_rng(), 50);
+        assert!(Chain::<u32>::new(1).generate_with_source(&mut XorShift(1), -1).is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use ::*;
+    #[test]
+    fn test_to_dot() {
+        let mut chain = Chain::new(1);
+        chain.train(vec![1, 2, 1, 3]);
+        let dot = chain.to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("\"__START__\" -> \"1\" [label=\"1\"];"));
+        assert!(dot.contains("\"1\" -> \"2\" [label=\"1\"];"));
+        assert!(dot.contains("\"1\" -> \"3\" [label=\"1\"];"));
+        assert!(dot.contains("\"2\" -> \"1\" [label=\"1\"];"));
+        assert!(dot.contains("\"3\" -> \"__END__\" [label=\"1\"];"));
+        assert_eq!(dot.matches("->").count(), 5);
+        assert_eq!(dot, chain.clone().to_dot());
 
-    macro_rules! test_get_link {
-        ($chain:expr, [$($key:expr),+]) => {{
-            let ref map = $chain.chain;
-            let key = vec![$(Some($key),)+];
-            assert_eq!(key.len(), $chain.order);
-            assert!(map.contains_key(&key));
-            map.get(&key)
-                .unwrap()
-        }}; 
+        let mut chain = Chain::new(2);
+        chain.train(vec![String::from("say"), String::from("\"hi\"")]);
+        let dot = chain.to_dot();
+        assert!(dot.contains("\"__START__ say\" -> \"say \\\"hi\\\"\" [label=\"1\"];"));
     }
 
-    macro_rules! test_link_weight {
-        ($link:expr, $key:expr, $weight:expr) => {
-            let link = $link;
-            let key = $key;
-            assert!(link.contains_key(&key));
-            assert_eq!(*link.get(&key).unwrap(), $weight);
+    #[test]
+    fn test_count_paths() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 3])
+            .train(vec![2, 3]);
+        // 1 3, 2 3, 1 2 3
+        assert_eq!(chain.count_paths(0), 0);
+        assert_eq!(chain.count_paths(2), 2);
+        assert_eq!(chain.count_paths(3), 3);
+        assert_eq!(chain.count_paths(100), 3);
+
+        // a cycle gives one more sequence for every item allowed
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 1, 1]);
+        assert_eq!(chain.count_paths(10), 9);
+
+        // 2 choices for each item saturates well before 100 items
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 1, 1, 2, 2]);
+        assert_eq!(chain.count_paths(100), u64::MAX);
+        assert_eq!(Chain::<u32>::new(1).count_paths(10), 0);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 1, 2, 1, 2, 1, 3])
+            .train(vec![4, 5]);
+        chain.prune(2);
+        test_link_weight!(test_get_link!(chain, [1]), Some(2), 3);
+        assert!(!test_get_link!(chain, [1]).contains_key(&Some(3)));
+        test_link_weight!(test_get_link!(chain, [2]), Some(1), 3);
+        // every link of these was pruned, so they are gone
+        assert!(!chain.chain.contains_key(&vec![Some(3)]));
+        assert!(!chain.chain.contains_key(&vec![Some(4)]));
+        assert!(!chain.chain.contains_key(&vec![None]));
+        assert!(chain.chain.values().all(|link| !link.is_empty()));
+        assert!(!chain.recency.contains_key(&vec![Some(4)]));
+        assert_eq!(chain.chain.len(), 2);
+        chain.prune(4);
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut chain = Chain::new(1);
+        chain.train(vec![1, 2, 1, 2, 1, 2, 1, 2, 3]);
+        test_link_weight!(test_get_link!(chain, [1]), Some(2), 4);
+        chain.decay(0.5);
+        test_link_weight!(test_get_link!(chain, [1]), Some(2), 2);
+        // 3 halves to 1.5, which rounds up
+        test_link_weight!(test_get_link!(chain, [2]), Some(1), 2);
+        // every weight rounds to zero, taking the starting node with them
+        chain.decay(0.1);
+        assert!(chain.chain.is_empty());
+        assert!(chain.recency.is_empty());
+    }
+
+    #[test]
+    fn test_prune_nodes() {
+        let mut chain = Chain::<u32>::new(1);
+        chain.train(vec![1, 2, 3, 1, 2, 4])
+            .train(vec![5, 1]);
+        assert_eq!(chain.chain.len(), 6);
+        chain.prune_nodes(2);
+        assert_eq!(chain.chain.len(), 3);
+        assert!(chain.chain.contains_key(&vec![None]));
+        assert!(chain.chain.contains_key(&vec![Some(1)]));
+        assert!(chain.chain.contains_key(&vec![Some(2)]));
+        chain.prune_nodes(0);
+        assert_eq!(chain.chain.len(), 3);
+        chain.prune_nodes(4);
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_generate_alternating() {
+        let mut a = Chain::<u32>::new(1);
+        a.chain = hashmap!{
+            vec![Some(1)] => hashmap!{Some(2) => 1},
+            vec![Some(2)] => hashmap!{Some(1) => 1},
         };
+        let mut b = Chain::<u32>::new(1);
+        b.chain = hashmap!{
+            vec![Some(1)] => hashmap!{Some(3) => 1},
+            vec![Some(2)] => hashmap!{Some(3) => 1},
+            vec![Some(3)] => hashmap!{Some(1) => 1},
+        };
+        for _ in 0 .. 20 {
+            // two items from a, then two from b
+            let sequence = Chain::generate_alternating(&a, &b, 2, 7);
+            assert_eq!(sequence.len(), 7);
+            assert_eq!(&sequence[3 ..], &[3, 1, 2, 1]);
+            // a has never seen [3], so b is used instead of ending early
+            let sequence = Chain::generate_alternating(&a, &b, 1, 6);
+            assert_eq!(&sequence[1 ..], &[if sequence[0] == 1 { 2 } else { 1 }, 3, 1, 3, 1]);
+        }
+        assert_eq!(Chain::generate_alternating(&a, &Chain::new(1), 1, 10).len(), 10);
+        assert!(Chain::<u32>::generate_alternating(&Chain::new(1), &Chain::new(1), 1, -1).is_empty());
     }
 
-    #[cfg(feature = "serde_cbor")]
     #[test]
-    fn test_cbor_serialize() {
+    fn test_compact() {
+        use rand::{SeedableRng, StdRng};
+
+        let mut chain = Chain::<u32>::new(2);
+        chain.train(vec![1, 2, 3]);
+        // nothing leads to [9, None], and it can't be started from
+        chain.chain.insert(vec![Some(9), None], hashmap!{Some(1) => 1});
+        // nothing leads to [8, 9] either, but a random start can choose it
+        chain.chain.insert(vec![Some(8), Some(9)], hashmap!{None => 1});
+        chain.rebuild_caches();
+        let before = (0 .. 20)
+            .map(|seed| chain.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), -1))
+            .collect::<Vec<_>>();
+        assert_eq!(chain.chain.len(), 6);
+        chain.compact();
+        assert_eq!(chain.chain.len(), 5);
+        assert!(!chain.contains_node(&[Some(9), None]));
+        assert!(chain.contains_node(&[Some(8), Some(9)]));
+        let after = (0 .. 20)
+            .map(|seed| chain.generate_with_rng(&mut StdRng::from_seed(&[seed][..]), -1))
+            .collect::<Vec<_>>();
+        assert_eq!(before, after);
+
+        // without complete nodes, any node can be a random start
+        let mut chain = Chain::<u32>::new(3);
+        chain.train(vec![1]);
+        chain.chain.insert(vec![Some(9), None, None], hashmap!{None => 1});
+        let trained = chain.clone();
+        assert_eq!(chain.compact(), &trained);
+    }
+
+    #[test]
+    fn test_generate_recent_start() {
         let mut chain = Chain::<u32>::new(1);
-        chain.train(vec![1, 2, 3])
-            .train(vec![2, 3, 4])
-            .train(vec![1, 3, 4]);
-        let cbor_vec = chain.to_cbor();
-        assert!(cbor_vec.is_ok());
-        let de = Chain::from_cbor(&cbor_vec.unwrap());
-        assert_eq!(de.unwrap(), chain);
+        chain.train(vec![1, 2])
+            .train(vec![3, 4])
+            .train(vec![5, 6]);
+        assert_eq!(chain.recency[&vec![Some(5u32)]], 3);
+        // a decay of zero only ever chooses the latest opening
+        for _ in 0 .. 20 {
+            assert_eq!(chain.generate_recent_start(0.0), vec![5, 6]);
+        }
+        chain.train(vec![1, 2]);
+        for _ in 0 .. 20 {
+            assert_eq!(chain.generate_recent_start(0.0), vec![1, 2]);
+        }
+        assert!(Chain::<u32>::new(1).generate_recent_start(0.5).is_empty());
     }
 
-    #[cfg(feature = "serde_yaml")]
     #[test]
-    fn test_yaml_serialize() {
+    fn test_absorbing_paths() {
         let mut chain = Chain::<u32>::new(1);
-        chain.train(vec![1, 2, 3])
-            .train(vec![2, 3, 4])
-            .train(vec![1, 3, 4]);
-        let yaml_str = chain.to_yaml();
-        assert!(yaml_str.is_ok());
-        let de = Chain::from_yaml(&yaml_str.unwrap());
-        assert_eq!(de.unwrap(), chain);
+        chain.train(vec![1, 2, 3, 1, 2, 3]);
+        assert!(chain.has_absorbing_paths());
+        assert!(chain.non_terminating_nodes().is_empty());
+
+        // 4 -> 5 -> 4 can never end
+        chain.chain.insert(vec![Some(4)], hashmap!{Some(5) => 1});
+        chain.chain.insert(vec![Some(5)], hashmap!{Some(4) => 1});
+        assert!(!chain.has_absorbing_paths());
+        let mut stuck = chain.non_terminating_nodes();
+        stuck.sort();
+        assert_eq!(stuck, vec![&vec![Some(4)], &vec![Some(5)]]);
+
+        // linking to a node that doesn't exist is a way out
+        chain.chain.insert(vec![Some(5)], hashmap!{Some(4) => 1, Some(6) => 1});
+        assert!(chain.has_absorbing_paths());
     }
 
     #[test]
-    fn test_order1_training() {
+    fn test_nodes_by_activity() {
         let mut chain = Chain::<u32>::new(1);
-        chain.train(vec![1, 2, 3])
-            .train(vec![2, 3, 4])
-            .train(vec![1, 3, 4]);
-        let link = test_get_link!(chain, [1u32]);
-        test_link_weight!(link, Some(2u32), 1);
-        test_link_weight!(link, Some(3u32), 1);
+        chain.train(vec![1, 2, 3, 1, 2, 3, 1, 4]);
+        let nodes = chain.nodes_by_activity();
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(nodes[0], (&vec![Some(1)], 3));
+        let totals = nodes.iter()
+            .map(|&(_, total)| total)
+            .collect::<Vec<_>>();
+        assert_eq!(totals, vec![3, 2, 2, 1, 1]);
+    }
 
-        let link = test_get_link!(chain, [2u32]);
-        test_link_weight!(link, Some(3u32), 2);
+    #[test]
+    fn test_from_iter_extend() {
+        let sequences = vec![vec![1u32, 2, 3], vec![2, 3, 4], vec![1, 3, 4]];
+        let mut trained = Chain::new(1);
+        for string in sequences.clone() {
+            trained.train(string);
+        }
+        let collected = sequences.clone()
+            .into_iter()
+            .collect::<Chain<u32>>();
+        assert_eq!(collected, trained);
 
-        let link = test_get_link!(chain, [3u32]);
-        test_link_weight!(link, None, 1);
-        test_link_weight!(link, Some(4u32), 2);
+        let mut trained = Chain::new(2);
+        trained.train(vec![5, 6, 7]);
+        let mut extended = trained.clone();
+        for string in sequences.clone() {
+            trained.train(string);
+        }
+        extended.extend(sequences);
+        assert_eq!(extended, trained);
+    }
 
-        let link = test_get_link!(chain, [4u32]);
-        test_link_weight!(link, None, 2);
+    #[test]
+    fn test_add() {
+        let mut a = Chain::<u32>::new(2);
+        a.train(vec![1, 2, 3, 4]);
+        let mut b = Chain::new(2);
+        b.train(vec![2, 3, 5])
+            .train(vec![1, 2, 3]);
+        let mut merged = a.clone();
+        merged.merge(&b);
+        assert_eq!(a.clone() + b.clone(), merged);
+        assert_eq!(a.clone() + &b, merged);
+
+        let mut total = a.clone();
+        total += &b;
+        assert_eq!(total, merged);
+        let mut total = a;
+        total += b;
+        assert_eq!(total, merged);
     }
 
     #[test]
-    fn test_order2_training() {
-        let mut chain = Chain::<u32>::new(2);
-        chain.train(vec![1, 2, 3])
-            .train(vec![2, 3, 4])
-            .train(vec![1, 3, 4]);
-        let link = test_get_link!(chain, [1u32, 2u32]);
-        test_link_weight!(link, Some(3u32), 1);
+    #[should_panic]
+    fn test_add_order_mismatch() {
+        let _ = Chain::<u32>::new(1) + Chain::new(2);
+    }
 
-        let link = test_get_link!(chain, [2u32, 3u32]);
-        test_link_weight!(link, None, 1);
-        test_link_weight!(link, Some(4u32), 1);
+    #[test]
+    fn test_try_merge() {
+        let mut chain1 = Chain::<u32>::new(1);
+        chain1.train(vec![1, 2, 3]);
+        let mut chain2 = Chain::<u32>::new(2);
+        chain2.train(vec![1, 2, 3]);
+        let before = chain1.clone();
+        assert_eq!(chain1.try_merge(&chain2).unwrap_err(),
+                   MergeError::OrderMismatch { self_order: 1, other_order: 2 });
+        assert_eq!(chain1, before);
 
-        let link = test_get_link!(chain, [3u32, 4u32]);
-        test_link_weight!(link, None, 2);
+        let mut chain3 = Chain::<u32>::new(1);
+        chain3.train(vec![1, 2, 4]);
+        chain1.try_merge(&chain3).unwrap();
+        test_link_weight!(test_get_link!(chain1, [1]), Some(2), 2);
+    }
 
-        let link = test_get_link!(chain, [1u32, 3u32]);
-        test_link_weight!(link, Some(4u32), 1);
+    #[test]
+    #[should_panic]
+    fn test_merge_order_mismatch() {
+        Chain::<u32>::new(1).merge(&Chain::new(2));
+    }
+
+    #[test]
+    fn test_blend() {
+        let mut chain1 = Chain::<u32>::new(1);
+        chain1.train(vec![1, 2, 3]);
+        let mut chain2 = Chain::new(1);
+        chain2.train(vec![1, 2, 4]);
+        let blended = Chain::blend(&[(&chain1, 1.5), (&chain2, 0.25)]).unwrap();
+        // 1.5 + 0.25 rounds to 2
+        test_link_weight!(test_get_link!(blended, [1]), Some(2), 2);
+        // 1.5 rounds to 2
+        test_link_weight!(test_get_link!(blended, [2]), Some(3), 2);
+        // 0.25 rounds to 0, dropping the link
+        assert!(!test_get_link!(blended, [2]).contains_key(&Some(4)));
+        assert!(!blended.chain.contains_key(&vec![Some(4)]));
+
+        let mut chain3 = Chain::new(2);
+        chain3.train(vec![1, 2, 3]);
+        assert_eq!(Chain::blend(&[(&chain1, 1.0), (&chain3, 1.0)]),
+                   Err(MergeError::OrderMismatch { self_order: 1, other_order: 2 }));
+        assert_eq!(Chain::<u32>::blend(&[]), Err(MergeError::Empty));
+    }
+
+    #[test]
+    fn test_generic_weights() {
+        let mut chain = Chain::<u32, u64>::with_weights(1);
+        chain.train(vec![1, 2, 3]);
+        test_link_weight!(test_get_link!(chain, [1]), Some(2), 1u64);
+        assert_eq!(chain.generate().last(), Some(&3));
+
+        // fractional weights survive blending without being rounded away
+        let mut chain1 = Chain::<u32, f64>::with_weights(1);
+        chain1.train(vec![1, 2, 3]);
+        let mut chain2 = Chain::with_weights(1);
+        chain2.train(vec![1, 2, 4]);
+        let blended = Chain::blend(&[(&chain1, 1.5), (&chain2, 0.25)]).unwrap();
+        test_link_weight!(test_get_link!(blended, [2]), Some(3), 1.5);
+        test_link_weight!(test_get_link!(blended, [2]), Some(4), 0.25);
+        assert_eq!(blended.probability(&[Some(2)], &Some(4)), 0.25 / 1.75);
+
+        // integer weights saturate rather than wrapping
+        let mut chain = Chain::<u32>::new(1);
+        chain.update_link_weight(&[Some(1)], &Some(2), u32::MAX);
+        chain.update_link(&[Some(1)], &Some(2));
+        test_link_weight!(test_get_link!(chain, [1]), Some(2), u32::MAX);
+    }
+
+    #[test]
+    fn test_diff_apply_delta() {
+        let mut base = Chain::<u32>::new(2);
+        base.train(vec![1, 2, 3])
+            .train(vec![5, 6]);
+        let mut updated = base.clone();
+        updated.train(vec![1, 2, 3, 4])
+            .train(vec![2, 3]);
+        // remove a node entirely
+        updated.chain.remove(&vec![Some(5), Some(6)]);
+
+        let delta = updated.diff(&base);
+        assert_eq!(delta.order(), 2);
+        assert!(!delta.is_empty());
+        let mut applied = base.clone();
+        applied.apply_delta(&delta);
+        assert_eq!(applied, updated);
+
+        assert!(updated.diff(&updated).is_empty());
     }
 
     #[test]
@@ -482,4 +7164,29 @@ mod tests {
         let link = test_get_link!(chain, [4u32, 1u32, 2u32]);
         test_link_weight!(link, Some(3u32), 1);
     }
+
+    #[test]
+    fn test_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+        type FixedChain<T> = Chain<T, u32, BuildHasherDefault<DefaultHasher>>;
+
+        let mut chain = FixedChain::<u32>::with_weights(1);
+        chain.train(vec![1, 2, 3])
+            .train(vec![1, 2, 3]);
+        test_link_weight!(test_get_link!(chain, [2]), Some(3), 2);
+        assert_eq!(chain.generate().last(), Some(&3));
+        assert_eq!(chain.iter_generate().last(), Some(3));
+
+        let mut strings = FixedChain::<String>::with_weights(1);
+        strings.train_string("the cat sat.");
+        assert_eq!(strings.generate_sentence(), "the cat sat.");
+
+        #[cfg(feature = "serde_cbor")]
+        assert_eq!(FixedChain::from_cbor(&chain.to_cbor().unwrap()).unwrap(), chain);
+        #[cfg(feature = "serde_json")]
+        assert_eq!(FixedChain::from_json(&chain.to_json().unwrap()).unwrap(), chain);
+        #[cfg(feature = "serde_yaml")]
+        assert_eq!(FixedChain::from_yaml(&chain.to_yaml().unwrap()).unwrap(), chain);
+    }
 }
@@ -0,0 +1,227 @@
+#![cfg(all(feature = "generator", feature = "serde_yaml"))]
+extern crate markov_chain;
+
+use markov_chain::Chain;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Runs `markov-util` with some arguments, feeding `stdin` to it.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_markov-util"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Gets a path in the temporary directory that is unique to a test.
+fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("markov-util-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_train_stdin() {
+    let path = temp_path("train-stdin.yaml");
+    let output = run(&["train", "-o", path.to_str().unwrap(), "-"], "the cat sat.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&path).unwrap();
+    let chain = Chain::<String>::from_yaml(&contents).unwrap();
+    assert_eq!(chain.generate_sentence(), "the cat sat.");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("    4 tokens from -\n"));
+}
+
+#[test]
+fn test_train_file() {
+    let input = temp_path("train-file.txt");
+    let path = temp_path("train-file.yaml");
+    // sentences that span lines are still read as one sentence
+    fs::write(&input, "the cat\nsat.\n").unwrap();
+    let output = run(&["train", "-o", path.to_str().unwrap(), input.to_str().unwrap(), "-"], "a dog ran.");
+    fs::remove_file(&input).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&path).unwrap();
+    let chain = Chain::<String>::from_yaml(&contents).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("    4 tokens from {}\n", input.display())));
+    assert!(stdout.contains("    4 tokens from -\n"));
+    assert_eq!(chain.transition_weight(&[Some(String::from("cat"))], &Some(String::from("sat"))), 1);
+    assert_eq!(chain.transition_weight(&[None], &Some(String::from("a"))), 1);
+}
+
+#[test]
+fn test_generate_stdin() {
+    let output = run(&["generate", "-s", "1", "-"], "the cat sat.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "the cat sat.");
+}
+
+#[test]
+fn test_merge_stdin() {
+    let path = temp_path("merge-stdin.yaml");
+    let output = run(&["merge", "-o", path.to_str().unwrap(), "-"], "the cat sat.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&path).unwrap();
+    let chain = Chain::<String>::from_yaml(&contents).unwrap();
+    assert_eq!(chain.generate_sentence(), "the cat sat.");
+}
+
+#[test]
+fn test_merge_files() {
+    let paths = (0 .. 3).map(|i| temp_path(&format!("merge-files-{}.yaml", i))).collect::<Vec<_>>();
+    for (path, text) in paths.iter().zip(&["the cat sat.", "the dog sat.", "a cat ran."]) {
+        let mut chain = Chain::<String>::new(1);
+        chain.train_string(text);
+        fs::write(path, chain.to_yaml().unwrap()).unwrap();
+    }
+    let merged = temp_path("merge-files.yaml");
+    let mut args = vec!["merge", "-o", merged.to_str().unwrap()];
+    args.extend(paths.iter().map(|path| path.to_str().unwrap()));
+    args.push("-");
+    let output = run(&args, "the end.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut contents = String::new();
+    File::open(&merged).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&merged).unwrap();
+    let chain = Chain::<String>::from_yaml(&contents).unwrap();
+    assert_eq!(chain.transition_weight(&[None], &Some(String::from("the"))), 3);
+    assert_eq!(chain.transition_weight(&[Some(String::from("cat"))], &Some(String::from("sat"))), 1);
+    assert_eq!(chain.transition_weight(&[Some(String::from("cat"))], &Some(String::from("ran"))), 1);
+
+    // every chain file must have the same order
+    let mut chain = Chain::<String>::new(2);
+    chain.train_string("the cat sat.");
+    fs::write(&paths[2], chain.to_yaml().unwrap()).unwrap();
+    let output = run(&["generate", paths[0].to_str().unwrap(), paths[1].to_str().unwrap(), paths[2].to_str().unwrap()], "");
+    for path in &paths {
+        fs::remove_file(path).unwrap();
+    }
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("could not merge {}", paths[2].display())), "{}", stderr);
+}
+
+#[test]
+fn test_min_weight() {
+    let path = temp_path("min-weight.yaml");
+    let output = run(&["train", "--min-weight", "2", "-o", path.to_str().unwrap(), "-"],
+                     "the cat sat. the dog sat.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // only "the" and "sat ." were seen twice
+    assert!(String::from_utf8_lossy(&output.stdout).contains("    4 edges pruned\n"));
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    let chain = Chain::<String>::from_yaml(&contents).unwrap();
+    assert_eq!(chain.edge_count(), 3);
+
+    let merged = temp_path("min-weight-merged.yaml");
+    let output = run(&["merge", "--min-weight", "3", "-o", merged.to_str().unwrap(), path.to_str().unwrap()], "");
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "    3 edges pruned\n");
+    let mut contents = String::new();
+    File::open(&merged).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&merged).unwrap();
+    assert!(Chain::<String>::from_yaml(&contents).unwrap().is_empty());
+
+    let output = run(&["train", "--min-weight", "x", "-o", "chain.yaml", "-"], "");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_stats() {
+    let path = temp_path("stats.yaml");
+    let output = run(&["train", "-o", path.to_str().unwrap(), "-"], "the cat sat. the dog sat. a cat ran.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run(&["stats", path.to_str().unwrap()], "");
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Order:        1\n"));
+    assert!(stdout.contains("Observations: 15\n"));
+    // the most frequent starting token is listed first
+    let the = stdout.find("  the\n").unwrap();
+    let a = stdout.find("  a\n").unwrap();
+    assert!(the < a);
+
+    let output = run(&["stats", "chain.txt"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+}
+
+#[test]
+fn test_generate_output() {
+    let path = temp_path("generate-output.txt");
+    fs::write(&path, "some longer text that should be overwritten\n").unwrap();
+    let output = run(&["generate", "-s", "1", "-o", path.to_str().unwrap(), "-"], "the cat sat.");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty());
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&path).unwrap();
+    // the file ends with a newline, like printed output
+    assert_eq!(contents, "the cat sat.\n");
+}
+
+#[test]
+fn test_merge_input_order() {
+    let path = temp_path("input-order.yaml");
+    let mut chain = Chain::<String>::new(1);
+    chain.train_string("the cat sat.");
+    fs::write(&path, chain.to_yaml().unwrap()).unwrap();
+    let merged = temp_path("input-order-merged.yaml");
+
+    // text given after a chain file is trained after everything in it
+    let mut clocks = Vec::new();
+    for args in &[[path.to_str().unwrap(), "-"], ["-", path.to_str().unwrap()]] {
+        let output = run(&["merge", "-o", merged.to_str().unwrap(), args[0], args[1]], "the dog sat.");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let mut contents = String::new();
+        File::open(&merged).unwrap().read_to_string(&mut contents).unwrap();
+        fs::remove_file(&merged).unwrap();
+        clocks.push(contents.lines().find(|line| line.starts_with("clock:")).map(String::from));
+    }
+    fs::remove_file(&path).unwrap();
+    assert_eq!(clocks, vec![Some(String::from("clock: 2")), Some(String::from("clock: 1"))]);
+}
+
+#[test]
+fn test_generate_seed_per_output() {
+    let text = "the cat sat. the dog sat. the cat ran. a dog ran. the rat sat. a cat ate.";
+    let outputs = |paragraphs: &str| {
+        let output = run(&["generate", "-c", "3", "-p", paragraphs, "-s", "3", "-S", "7", "-"], text);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .split("\n\n---\n\n")
+            .map(|output| output.split("\n\n").next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    // the first paragraph of each output is the same however many paragraphs
+    // came before it
+    let short = outputs("1");
+    assert_eq!(short.len(), 3);
+    assert_eq!(short, outputs("4"));
+    assert_eq!(short, outputs("1"));
+}